@@ -0,0 +1,34 @@
+//! Replay a fixture on localhost until interrupted.
+//!
+//! ```shell
+//! cargo run -p jarm --features mock --example mock_server -- jarm/tests/fixtures/openssl.toml
+//! jarm-rs -t 127.0.0.1:<port>
+//! ```
+use jarm::Fixture;
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: mock_server <fixture.toml>");
+            std::process::exit(2);
+        }
+    };
+    let fixture = match Fixture::load(&path) {
+        Ok(fixture) => fixture,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let server = fixture.serve().expect("bind a port on localhost");
+    println!(
+        "{} on {}, expect {}",
+        fixture.name,
+        server.addr(),
+        fixture.fingerprint
+    );
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,9 @@
+#![no_main]
+
+use jarm::{ProbeResult, ServerHello};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ServerHello::parse(data);
+    let _ = ProbeResult::from_response("fuzz", data);
+});
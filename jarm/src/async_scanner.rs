@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::{timeout, timeout_at, Instant};
+
+use crate::builder::Config;
+use crate::connector::Target;
+use crate::record::Response;
+use crate::{
+    leaf_certificate, read_probe, Handshake, JarmError, JarmFingerprint, Part, ProbeSet,
+    ScanResult, ScannerBuilder, StartTls, MAX_RESPONSE_LEN,
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::hello_server;
+    use crate::{AsyncScanner, JarmError, Scanner};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn same_as_blocking_scanner() {
+        let port = hello_server().port();
+        let scanner = AsyncScanner::new("127.0.0.1".to_string(), port)
+            .await
+            .unwrap();
+        let result = scanner.scan().await;
+        assert_eq!(result.probes.len(), 10);
+        assert!(result.probes.iter().all(|p| p.error.is_none()));
+        assert_eq!(result.probes[6].name, "tls1_3_forward");
+        let blocking = Scanner::new("127.0.0.1".to_string(), port).unwrap();
+        assert_eq!(result.fingerprint, blocking.fingerprint());
+    }
+
+    #[tokio::test]
+    async fn probe_timeout() {
+        // accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        let scanner = Scanner::builder("127.0.0.1", port)
+            .read_timeout(Duration::from_millis(200))
+            .build_async()
+            .await
+            .unwrap();
+        let result = scanner.scan().await;
+        assert_eq!(result.error(), Some(&JarmError::ReadTimeout));
+        assert!(result.fingerprint.is_zero());
+    }
+}
+
+/// A [`Scanner`](crate::Scanner) on tokio that sends the ten probes concurrently.
+///
+/// Each probe has its own timeouts. Dropping the future returned by
+/// [`AsyncScanner::scan`] cancels the probes still in flight.
+pub struct AsyncScanner {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) addrs: Vec<SocketAddr>,
+    pub(crate) probes: ProbeSet,
+    pub(crate) config: Config,
+}
+
+impl AsyncScanner {
+    pub async fn new(host: String, port: u16) -> Result<Self, JarmError> {
+        ScannerBuilder::new(host, port).build_async().await
+    }
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    /// The server name the probes carry, if any.
+    pub fn sni(&self) -> Option<&str> {
+        self.config.sni.as_deref()
+    }
+    pub fn probe_set(&self) -> &ProbeSet {
+        &self.probes
+    }
+    /// The address [`AsyncScanner::scan`] connects to, `None` when a proxy resolves the host.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addrs.first().copied()
+    }
+    /// Every resolved address, in the order of the address family preference.
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+    fn target(&self) -> Target {
+        match self.addr() {
+            Some(addr) => Target::Addr(addr),
+            None => Target::Host(self.host.clone(), self.port),
+        }
+    }
+    async fn connect(target: &Target, config: &Config) -> io::Result<TcpStream> {
+        // #Connectors are blocking, run them off the runtime
+        if let Some(connector) = config.connector.clone() {
+            let target = target.clone();
+            let (local, timeout) = (config.bind, config.connect_timeout);
+            let stream =
+                tokio::task::spawn_blocking(move || connector.connect(&target, local, timeout))
+                    .await
+                    .map_err(io::Error::other)??;
+            stream.set_nonblocking(true)?;
+            return TcpStream::from_std(stream);
+        }
+        let addr = match target {
+            Target::Addr(addr) => *addr,
+            Target::Host(host, port) => tokio::net::lookup_host((host.as_str(), *port))
+                .await?
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, host.clone()))?,
+        };
+        let local = match config.bind {
+            None => return TcpStream::connect(addr).await,
+            Some(local) => local,
+        };
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        socket.set_reuseaddr(true)?;
+        socket.bind(local)?;
+        socket.connect(addr).await
+    }
+    // #The async twin of `starttls::upgrade`
+    async fn upgrade(
+        stream: &mut TcpStream,
+        protocol: StartTls,
+        domain: &str,
+        deadline: Instant,
+    ) -> Result<(), JarmError> {
+        let mut data = [0_u8; 1484];
+        for step in protocol.steps(domain) {
+            stream
+                .write_all(&step.send)
+                .await
+                .map_err(JarmError::read)?;
+            let mut reply = Vec::new();
+            while !step.check(&reply)? {
+                match timeout_at(deadline, stream.read(&mut data)).await {
+                    Ok(Ok(0)) => return Err(JarmError::ConnectionClosed),
+                    Ok(Ok(n)) => reply.extend_from_slice(&data[..n]),
+                    Ok(Err(e)) => return Err(JarmError::read(e)),
+                    Err(_) => return Err(JarmError::ReadTimeout),
+                }
+            }
+        }
+        Ok(())
+    }
+    async fn send_packet(
+        target: &Target,
+        domain: &str,
+        payload: &[u8],
+        config: &Config,
+    ) -> Result<(Vec<u8>, usize), JarmError> {
+        let mut data = [0_u8; 1484];
+
+        if let Some(limiter) = &config.rate_limiter {
+            tokio::time::sleep_until(limiter.reserve().into()).await;
+        }
+        let mut stream = timeout(
+            config.connect_timeout,
+            AsyncScanner::connect(target, config),
+        )
+        .await
+        .map_err(|_| JarmError::ConnectTimeout)?
+        .map_err(JarmError::connect)?;
+        if let Some(protocol) = config.starttls {
+            let deadline = Instant::now() + config.read_timeout;
+            AsyncScanner::upgrade(&mut stream, protocol, domain, deadline).await?;
+        }
+        stream.write_all(payload).await.map_err(JarmError::read)?;
+        // #Keep reading until the server hello, the flight for certificates, or an alert is complete
+        let deadline = Instant::now() + config.read_timeout;
+        let mut response = Response::new(config.certificates);
+        while !response.is_complete() && response.len() < MAX_RESPONSE_LEN {
+            match timeout_at(deadline, stream.read(&mut data)).await {
+                Ok(Ok(0)) => break,
+                Ok(Ok(n)) => response.push(&data[..n]),
+                Ok(Err(e)) if response.is_empty() => return Err(JarmError::read(e)),
+                Err(_) if response.is_empty() => return Err(JarmError::ReadTimeout),
+                _ => break,
+            }
+        }
+        if response.is_empty() {
+            return Err(JarmError::ConnectionClosed);
+        }
+        Ok((response.data(), response.len()))
+    }
+    async fn send_packet_with_retries(
+        target: Target,
+        domain: String,
+        payload: Vec<u8>,
+        config: Config,
+    ) -> Result<(Vec<u8>, usize), JarmError> {
+        let mut attempt = 0;
+        loop {
+            match AsyncScanner::send_packet(&target, &domain, &payload, &config).await {
+                Err(e) if e.is_retryable() && attempt < config.retries => {
+                    tokio::time::sleep(config.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+    /// The ClientHello each probe would send, identical between calls with a
+    /// [`seed`](crate::ScannerBuilder::seed).
+    pub fn client_hellos(&self) -> Vec<Vec<u8>> {
+        self.config.client_hellos(self.probes.probes())
+    }
+    /// Send the ten probes concurrently and return the fingerprint along with each probe's result.
+    pub async fn scan(&self) -> ScanResult {
+        self.scan_target(self.target()).await
+    }
+    /// Scan every resolved address, one after the other.
+    ///
+    /// Empty when a proxy resolves the host, as there is no address to scan.
+    pub async fn scan_all(&self) -> BTreeMap<IpAddr, ScanResult> {
+        let mut results = BTreeMap::new();
+        for addr in &self.addrs {
+            results.insert(addr.ip(), self.scan_target(Target::Addr(*addr)).await);
+        }
+        results
+    }
+    async fn scan_target(&self, target: Target) -> ScanResult {
+        let mut probes = JoinSet::new();
+        let domain = self.config.sni.as_ref().unwrap_or(&self.host);
+        let specs = self.probes.probes();
+        let hellos = self.config.client_hellos(specs);
+        for (index, (spec, payload)) in specs.iter().zip(hellos).enumerate() {
+            let name = spec.name.clone();
+            let config = self.config.clone();
+            let target = target.clone();
+            let domain = domain.clone();
+            let delay = self.config.probe_delay * index as u32;
+            probes.spawn(async move {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+                let response =
+                    AsyncScanner::send_packet_with_retries(target, domain, payload, config).await;
+                (index, read_probe(&name, response))
+            });
+        }
+        let mut results: Vec<Option<(Part, _)>> = (0..specs.len()).map(|_| None).collect();
+        while let Some(joined) = probes.join_next().await {
+            if let Ok((index, result)) = joined {
+                results[index] = Some(result);
+            }
+        }
+        let mut parts = Vec::new();
+        let mut probe_results = Vec::new();
+        for (spec, result) in specs.iter().zip(results) {
+            let (part, probe) = result.unwrap_or_else(|| {
+                read_probe(
+                    &spec.name,
+                    Err(JarmError::Io("probe task failed".to_string())),
+                )
+            });
+            parts.push(part);
+            probe_results.push(probe);
+        }
+        let handshake = self.handshake(&target).await;
+        ScanResult {
+            addr: target.addr(),
+            fingerprint: JarmFingerprint::from_parts(&parts),
+            probe_set: self.probes.name().to_string(),
+            probes: probe_results,
+            certificate: leaf_certificate(&parts),
+            handshake,
+            matches: Vec::new(),
+        }
+    }
+    // #The full handshake is blocking, like the connectors
+    #[cfg(feature = "handshake")]
+    async fn handshake(&self, target: &Target) -> Option<Handshake> {
+        if !self.config.full_handshake {
+            return None;
+        }
+        let (host, port, config) = (self.host.clone(), self.port, self.config.clone());
+        let target = target.clone();
+        tokio::task::spawn_blocking(move || {
+            crate::handshake::follow_up(&target, &host, port, &config)
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+    #[cfg(not(feature = "handshake"))]
+    async fn handshake(&self, _target: &Target) -> Option<Handshake> {
+        None
+    }
+    pub async fn fingerprint(&self) -> JarmFingerprint {
+        self.scan().await.fingerprint
+    }
+}
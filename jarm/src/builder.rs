@@ -0,0 +1,450 @@
+use rand::rngs::StdRng;
+use rand::{thread_rng, RngCore, SeedableRng};
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::connector::{self, Connector, Target};
+use crate::{JarmError, ProbeSet, ProbeSpec, RateLimiter, Scanner, StartTls};
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::listen;
+    use crate::{AddressFamily, JarmError, RateLimiter, Scanner};
+    use std::io::{Read, Write};
+    use std::net::SocketAddr;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    // Send the client hellos back to the test and close the connection
+    fn server(drop_first: usize) -> (SocketAddr, mpsc::Receiver<Vec<u8>>) {
+        let (tx, rx) = mpsc::channel();
+        let addr = listen(move |i, mut stream| {
+            if i < drop_first {
+                return;
+            }
+            let mut hello = vec![0; 4096];
+            let n = stream.read(&mut hello).unwrap_or(0);
+            hello.truncate(n);
+            let _ = tx.send(hello);
+            let _ = stream.write_all(b"\x15\x03\x03\x00\x02\x02\x28");
+        });
+        (addr, rx)
+    }
+
+    #[test]
+    fn sni_override_and_explicit_addr() {
+        let (addr, hellos) = server(0);
+        let scanner = Scanner::builder("does-not-resolve.invalid", 443)
+            .addr(addr)
+            .sni("backend.example")
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .unwrap();
+        let result = scanner.scan();
+        assert!(matches!(result.error(), Some(JarmError::Alert { .. })));
+        let hello = hellos.recv().unwrap();
+        let hello = String::from_utf8_lossy(&hello);
+        assert!(hello.contains("backend.example"));
+        assert!(!hello.contains("does-not-resolve"));
+    }
+
+    #[test]
+    fn no_sni() {
+        let (addr, hellos) = server(0);
+        let with_sni = Scanner::builder("127.0.0.1", addr.port()).build().unwrap();
+        with_sni.scan();
+        let without_sni = Scanner::builder("127.0.0.1", addr.port())
+            .no_sni()
+            .build()
+            .unwrap();
+        without_sni.scan();
+        let hellos: Vec<Vec<u8>> = hellos.try_iter().collect();
+        assert_eq!(hellos.len(), 20);
+        // the server name extension is 9 bytes plus the host
+        assert_eq!(hellos[0].len(), hellos[10].len() + 9 + "127.0.0.1".len());
+    }
+
+    #[test]
+    fn address_family() {
+        let addrs: Vec<SocketAddr> = ["[::1]:443", "127.0.0.1:443", "[::2]:443", "127.0.0.1:443"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let ips = |family: AddressFamily| -> Vec<String> {
+            family
+                .select(addrs.iter().copied())
+                .iter()
+                .map(|a| a.ip().to_string())
+                .collect()
+        };
+        assert_eq!(ips(AddressFamily::Any), ["::1", "127.0.0.1", "::2"]);
+        assert_eq!(ips(AddressFamily::Ipv4), ["127.0.0.1"]);
+        assert_eq!(ips(AddressFamily::Ipv6), ["::1", "::2"]);
+        assert_eq!(ips(AddressFamily::PreferIpv4), ["127.0.0.1", "::1", "::2"]);
+        assert_eq!(ips(AddressFamily::PreferIpv6), ["::1", "::2", "127.0.0.1"]);
+    }
+
+    #[test]
+    fn scan_all_keyed_by_ip() {
+        let (addr, _hellos) = server(0);
+        let scanner = Scanner::builder("127.0.0.1", addr.port())
+            .address_family(AddressFamily::Ipv4)
+            .build()
+            .unwrap();
+        let results = scanner.scan_all();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[&addr.ip()].addr, Some(addr));
+        assert!(Scanner::builder("127.0.0.1", 443)
+            .address_family(AddressFamily::Ipv6)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn probe_delay_and_rate_limit() {
+        let (addr, _hellos) = server(0);
+        let start = Instant::now();
+        Scanner::builder("127.0.0.1", addr.port())
+            .probe_delay(Duration::from_millis(20))
+            .build()
+            .unwrap()
+            .scan();
+        assert!(start.elapsed() >= Duration::from_millis(9 * 20));
+
+        let limiter = RateLimiter::per_second(100);
+        let start = Instant::now();
+        for _ in 0..2 {
+            Scanner::builder("127.0.0.1", addr.port())
+                .rate_limiter(limiter.clone())
+                .build()
+                .unwrap()
+                .scan();
+        }
+        // 20 connection attempts 10ms apart
+        assert!(start.elapsed() >= Duration::from_millis(19 * 10));
+    }
+
+    #[test]
+    fn retries() {
+        let (addr, hellos) = server(2);
+        let scanner = Scanner::builder("127.0.0.1", addr.port())
+            .read_timeout(Duration::from_secs(5))
+            .retries(2)
+            .backoff(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let result = scanner.scan();
+        // the first probe was dropped twice before it got an answer
+        assert!(matches!(
+            result.probes[0].error,
+            Some(JarmError::Alert { .. })
+        ));
+        assert_eq!(hellos.try_iter().count(), 10);
+    }
+}
+
+/// How a scanner connects and what it sends, shared by the blocking and async scanners.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) connect_timeout: Duration,
+    pub(crate) read_timeout: Duration,
+    pub(crate) retries: u32,
+    pub(crate) backoff: Duration,
+    pub(crate) bind: Option<SocketAddr>,
+    pub(crate) sni: Option<String>,
+    pub(crate) connector: Option<Arc<dyn Connector>>,
+    pub(crate) starttls: Option<StartTls>,
+    pub(crate) probe_delay: Duration,
+    pub(crate) rate_limiter: Option<RateLimiter>,
+    pub(crate) seed: Option<u64>,
+    pub(crate) certificates: bool,
+    #[cfg(feature = "handshake")]
+    pub(crate) full_handshake: bool,
+    #[cfg(feature = "handshake")]
+    pub(crate) http: Option<bool>,
+}
+
+impl Config {
+    // #How long to wait before retry `attempt`, doubling each time
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.min(16))
+    }
+    // #The ClientHello of each probe, the same for every scan when there is a seed
+    pub(crate) fn client_hellos(&self, probes: &[ProbeSpec]) -> Vec<Vec<u8>> {
+        let mut rng: Box<dyn RngCore> = match self.seed {
+            Some(seed) => Box::new(StdRng::seed_from_u64(seed)),
+            None => Box::new(thread_rng()),
+        };
+        probes
+            .iter()
+            .map(|spec| spec.build_packet(self.sni.as_deref(), &mut *rng))
+            .collect()
+    }
+    pub(crate) fn connect(&self, target: &Target) -> io::Result<TcpStream> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.wait();
+        }
+        if let Some(connector) = &self.connector {
+            return connector.connect(target, self.bind, self.connect_timeout);
+        }
+        let addr = connector::resolve(target)?;
+        connector::connect_from(addr, self.bind, self.connect_timeout)
+    }
+}
+
+/// Which resolved addresses to scan and in what order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressFamily {
+    /// Keep the order of the resolver
+    #[default]
+    Any,
+    Ipv4,
+    Ipv6,
+    PreferIpv4,
+    PreferIpv6,
+}
+
+impl AddressFamily {
+    fn select(&self, addrs: impl Iterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+        let mut selected: Vec<SocketAddr> = Vec::new();
+        for addr in addrs {
+            if !selected.iter().any(|a| a.ip() == addr.ip()) {
+                selected.push(addr);
+            }
+        }
+        match self {
+            AddressFamily::Any => {}
+            AddressFamily::Ipv4 => selected.retain(|a| a.is_ipv4()),
+            AddressFamily::Ipv6 => selected.retain(|a| a.is_ipv6()),
+            AddressFamily::PreferIpv4 => selected.sort_by_key(|a| a.is_ipv6()),
+            AddressFamily::PreferIpv6 => selected.sort_by_key(|a| a.is_ipv4()),
+        }
+        selected
+    }
+}
+
+/// Configures a [`Scanner`].
+///
+/// ```no_run
+/// use jarm::Scanner;
+/// use std::time::Duration;
+///
+/// let scanner = Scanner::builder("www.salesforce.com", 443)
+///     .connect_timeout(Duration::from_secs(5))
+///     .read_timeout(Duration::from_secs(10))
+///     .retries(2)
+///     .build()
+///     .unwrap();
+/// println!("{}", scanner.fingerprint());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScannerBuilder {
+    host: String,
+    port: u16,
+    addr: Option<SocketAddr>,
+    family: AddressFamily,
+    probes: ProbeSet,
+    config: Config,
+}
+
+impl ScannerBuilder {
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        let host = host.into();
+        ScannerBuilder {
+            host: host.clone(),
+            port,
+            addr: None,
+            family: AddressFamily::Any,
+            probes: ProbeSet::jarm(),
+            config: Config {
+                connect_timeout: Duration::from_secs(30),
+                read_timeout: Duration::from_secs(30),
+                retries: 0,
+                backoff: Duration::from_millis(500),
+                bind: None,
+                sni: Some(host),
+                connector: None,
+                starttls: None,
+                probe_delay: Duration::ZERO,
+                rate_limiter: None,
+                seed: None,
+                certificates: false,
+                #[cfg(feature = "handshake")]
+                full_handshake: false,
+                #[cfg(feature = "handshake")]
+                http: None,
+            },
+        }
+    }
+    /// How long to wait for each TCP connect, 30 seconds by default.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.connect_timeout = timeout;
+        self
+    }
+    /// How long to wait for each server hello, 30 seconds by default.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = timeout;
+        self
+    }
+    /// Retry a probe this many times when it times out or the connection drops.
+    ///
+    /// Refused connections, alerts and malformed responses are not retried.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.config.retries = retries;
+        self
+    }
+    /// Wait before the first retry, doubled for every retry after that.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.config.backoff = backoff;
+        self
+    }
+    /// Wait this long between the probes sent to the host, for fragile servers.
+    ///
+    /// The async scanner starts probe `n` after `n` times the delay.
+    pub fn probe_delay(mut self, delay: Duration) -> Self {
+        self.config.probe_delay = delay;
+        self
+    }
+    /// Share `limiter` to cap connection attempts, retries included, across scanners.
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.config.rate_limiter = Some(limiter);
+        self
+    }
+    /// Send these probes instead of the ten of JARM.
+    pub fn probes(mut self, probes: ProbeSet) -> Self {
+        self.probes = probes;
+        self
+    }
+    /// Draw the random bytes and GREASE values of the probes from a seeded RNG.
+    ///
+    /// Every scan then sends the same packets, to reproduce a capture byte for byte.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+    /// Keep reading the handshake flight after a TLS 1.2 server hello, up to the
+    /// ServerHelloDone, for the certificate in [`ScanResult::certificate`](crate::ScanResult::certificate).
+    ///
+    /// Probes take longer and read more, the fingerprint stays the same.
+    pub fn certificates(mut self, certificates: bool) -> Self {
+        self.config.certificates = certificates;
+        self
+    }
+    /// After the probes, complete one handshake with rustls, certificate
+    /// verification disabled, for [`ScanResult::handshake`](crate::ScanResult::handshake).
+    ///
+    /// It reads the certificates TLS 1.3 encrypts, and the response to a
+    /// `GET /` on the [`HTTP_PORTS`](crate::HTTP_PORTS).
+    #[cfg(feature = "handshake")]
+    pub fn full_handshake(mut self, full_handshake: bool) -> Self {
+        self.config.full_handshake = full_handshake;
+        self
+    }
+    /// Send the `GET /` of the full handshake whatever the port, or never.
+    #[cfg(feature = "handshake")]
+    pub fn http(mut self, http: bool) -> Self {
+        self.config.http = Some(http);
+        self
+    }
+    /// Local address, and port unless it is 0, to connect from.
+    ///
+    /// With a [`connector`](ScannerBuilder::connector) it is passed on to
+    /// [`Connector::connect`], the proxies of this crate connect to the proxy
+    /// from it.
+    pub fn bind(mut self, local: SocketAddr) -> Self {
+        self.config.bind = Some(local);
+        self
+    }
+    /// Server name to send instead of the target host.
+    pub fn sni(mut self, sni: impl Into<String>) -> Self {
+        self.config.sni = Some(sni.into());
+        self
+    }
+    /// Leave the server name extension out of the probes.
+    pub fn no_sni(mut self) -> Self {
+        self.config.sni = None;
+        self
+    }
+    /// Connect to this address instead of resolving the host.
+    pub fn addr(mut self, addr: SocketAddr) -> Self {
+        self.addr = Some(addr);
+        self
+    }
+    /// Open connections with `connector`, e.g. a [`Socks5Proxy`](crate::Socks5Proxy).
+    ///
+    /// The host is not resolved when the connector does its own DNS.
+    pub fn connector(mut self, connector: impl Connector + 'static) -> Self {
+        self.config.connector = Some(Arc::new(connector));
+        self
+    }
+    /// Upgrade a plaintext protocol with STARTTLS before sending each probe.
+    ///
+    /// The upgrade shares the read timeout, XMPP uses the SNI as the server name.
+    pub fn starttls(mut self, protocol: StartTls) -> Self {
+        self.config.starttls = Some(protocol);
+        self
+    }
+    /// Which of the resolved A and AAAA addresses to use, and in what order.
+    pub fn address_family(mut self, family: AddressFamily) -> Self {
+        self.family = family;
+        self
+    }
+    fn select(
+        &self,
+        addrs: impl Iterator<Item = SocketAddr>,
+    ) -> Result<Vec<SocketAddr>, JarmError> {
+        let addrs = self.family.select(addrs);
+        if addrs.is_empty() {
+            return Err(JarmError::Resolve(self.host.clone()));
+        }
+        Ok(addrs)
+    }
+    fn remote_dns(&self) -> bool {
+        self.config
+            .connector
+            .as_ref()
+            .is_some_and(|c| c.remote_dns())
+    }
+    pub fn build(self) -> Result<Scanner, JarmError> {
+        let addrs = match self.addr {
+            Some(addr) => vec![addr],
+            None if self.remote_dns() => Vec::new(),
+            None => {
+                let addrs = format!("{}:{}", self.host, self.port)
+                    .to_socket_addrs()
+                    .map_err(|_| JarmError::Resolve(self.host.clone()))?;
+                self.select(addrs)?
+            }
+        };
+        Ok(Scanner {
+            host: self.host,
+            port: self.port,
+            addrs,
+            probes: self.probes,
+            config: self.config,
+        })
+    }
+    /// Build an [`AsyncScanner`](crate::AsyncScanner), resolving the host on tokio.
+    #[cfg(feature = "async")]
+    pub async fn build_async(self) -> Result<crate::AsyncScanner, JarmError> {
+        let addrs = match self.addr {
+            Some(addr) => vec![addr],
+            None if self.remote_dns() => Vec::new(),
+            None => {
+                let addrs = tokio::net::lookup_host(format!("{}:{}", self.host, self.port))
+                    .await
+                    .map_err(|_| JarmError::Resolve(self.host.clone()))?;
+                self.select(addrs)?
+            }
+        };
+        Ok(crate::AsyncScanner {
+            host: self.host,
+            port: self.port,
+            addrs,
+            probes: self.probes,
+            config: self.config,
+        })
+    }
+}
@@ -0,0 +1,496 @@
+use rand::thread_rng;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::parser::Reader;
+use crate::pcap::{segments, Segment, ACK, FIN, RST, SYN};
+use crate::record::Response;
+use crate::{
+    leaf_certificate, read_probe, JarmError, JarmFingerprint, Part, ProbeResult, ProbeSet,
+    ScanResult,
+};
+
+#[cfg(test)]
+mod tests {
+    use crate::capture::{hello_shape, probe_shapes};
+    use crate::pcap::tests::{frame, pcap, pcapng};
+    use crate::pcap::{ACK, FIN, SYN};
+    use crate::test_support::server_hello;
+    use crate::{Capture, CaptureError, JarmError, JarmFingerprint, Part, ProbeSet};
+    use rand::thread_rng;
+    use std::net::SocketAddr;
+    use std::time::SystemTime;
+
+    const ALERT: &[u8] = b"\x15\x03\x03\x00\x02\x02\x28";
+
+    // One probe connection: handshake, the probe, the reply in two segments and a FIN
+    fn connection(frames: &mut Vec<Vec<u8>>, client_port: u16, payload: &[u8], reply: &[u8]) {
+        let client = SocketAddr::from(([10, 0, 0, 1], client_port));
+        let server: SocketAddr = "10.0.0.2:443".parse().unwrap();
+        let half = reply.len() / 2;
+        frames.push(frame(client, server, 100, SYN, b""));
+        frames.push(frame(server, client, 500, SYN | ACK, b""));
+        frames.push(frame(client, server, 101, ACK, payload));
+        // the second half arrives first
+        frames.push(frame(
+            server,
+            client,
+            501 + half as u32,
+            ACK,
+            &reply[half..],
+        ));
+        frames.push(frame(server, client, 501, ACK, &reply[..half]));
+        frames.push(frame(
+            server,
+            client,
+            501 + reply.len() as u32,
+            ACK | FIN,
+            b"",
+        ));
+    }
+
+    #[test]
+    fn shapes() {
+        let shapes = probe_shapes();
+        for (i, probe) in ProbeSet::jarm().probes().iter().enumerate() {
+            // the random parts and the server name do not change the shape
+            let (shape, sni) =
+                hello_shape(&probe.build_packet(Some("example.com"), &mut thread_rng())).unwrap();
+            assert_eq!(sni.as_deref(), Some("example.com"));
+            assert_eq!(shapes.iter().position(|s| *s == shape), Some(i));
+        }
+        assert!(hello_shape(b"GET / HTTP/1.1\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn fingerprint_from_capture() {
+        let hello = server_hello();
+        let mut frames = Vec::new();
+        // two scans of the same server, the second misses its last probe
+        for scan in 0..2u16 {
+            for (i, probe) in ProbeSet::jarm().probes().iter().enumerate() {
+                if scan == 1 && i == 9 {
+                    continue;
+                }
+                let reply = if i % 2 == 0 { &hello[..] } else { ALERT };
+                let port = 40000 + scan * 100 + i as u16;
+                connection(
+                    &mut frames,
+                    port,
+                    &probe.build_packet(Some("example.com"), &mut thread_rng()),
+                    reply,
+                );
+            }
+        }
+        // a connection that is not a JARM probe
+        connection(
+            &mut frames,
+            50000,
+            b"GET / HTTP/1.1\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\n\r\n",
+        );
+
+        let capture = Capture::parse(&pcap(&frames)).unwrap();
+        let scans = capture.scans();
+        assert_eq!(scans.len(), 2);
+        let parts: Vec<Part> = (0..10)
+            .map(|i| match i % 2 {
+                0 => Part::new(&hello).unwrap(),
+                _ => Part::default(),
+            })
+            .collect();
+        let expected = JarmFingerprint::from_parts(&parts);
+        let first = &scans[0];
+        assert_eq!(
+            first.client,
+            "10.0.0.1".parse::<std::net::IpAddr>().unwrap()
+        );
+        assert_eq!(first.sni.as_deref(), Some("example.com"));
+        assert_eq!(first.result.addr, Some("10.0.0.2:443".parse().unwrap()));
+        assert_eq!(first.result.fingerprint, expected);
+        assert_eq!(first.result.probes[0].cipher.as_deref(), Some("c02f"));
+        assert_eq!(first.result.probes[0].bytes_read, hello.len());
+        assert_eq!(
+            first.result.probes[1].error.as_ref().map(JarmError::kind),
+            Some("alert")
+        );
+        let second = &scans[1];
+        assert!(second.timestamp > first.timestamp);
+        assert_eq!(second.result.probes[9].error, Some(JarmError::NotCaptured));
+        assert_eq!(second.result.probes[9].name, "tls1_3_middle_out");
+
+        assert!(matches!(
+            Capture::load("missing.pcap"),
+            Err(CaptureError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn timestamp_out_of_range() {
+        let hello = server_hello();
+        let set = ProbeSet::jarm();
+        let mut frames = Vec::new();
+        connection(
+            &mut frames,
+            40000,
+            &set.probes()[0].build_packet(Some("example.com"), &mut thread_rng()),
+            &hello,
+        );
+        // whole seconds and the largest tick count
+        let capture = Capture::parse(&pcapng(&frames, 0, u64::MAX)).unwrap();
+        let scans = capture.scans();
+        assert_eq!(scans.len(), 1);
+        assert_eq!(scans[0].timestamp, SystemTime::UNIX_EPOCH);
+        assert_eq!(scans[0].result.probes[0].cipher.as_deref(), Some("c02f"));
+    }
+}
+
+/// Why a capture could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureError {
+    Io(String),
+    /// The file is not a pcap or pcapng capture
+    Format(String),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Io(e) => write!(f, "io error: {}", e),
+            CaptureError::Format(e) => write!(f, "invalid capture: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+/// A JARM scan recovered from a packet capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedScan {
+    /// The host that sent the probes
+    pub client: IpAddr,
+    /// The server name the probes carried
+    pub sni: Option<String>,
+    /// When the first probe connection started
+    pub timestamp: SystemTime,
+    /// `addr` is the server, probes missing from the capture fail with [`JarmError::NotCaptured`]
+    pub result: ScanResult,
+}
+
+/// The JARM scans in a pcap or pcapng file, fingerprinted offline.
+///
+/// Connections whose client sent one of the ten JARM probes are matched up
+/// by client, server and server name, and the server's reply is parsed the
+/// same way as in a live scan. Probes sent after a STARTTLS upgrade are not
+/// recognised.
+///
+/// ```no_run
+/// use jarm::Capture;
+///
+/// let capture = Capture::load("incident.pcapng").unwrap();
+/// for scan in capture.scans() {
+///     println!("{:?} {}", scan.result.addr, scan.result.fingerprint);
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capture {
+    scans: Vec<CapturedScan>,
+}
+
+impl Capture {
+    /// Read a `.pcap` or `.pcapng` file, told apart by its magic number.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        let data = std::fs::read(path).map_err(|e| CaptureError::Io(e.to_string()))?;
+        Capture::parse(&data)
+    }
+    pub fn parse(data: &[u8]) -> Result<Self, CaptureError> {
+        let connections = connections(segments(data)?);
+        Ok(Capture {
+            scans: scans(&connections),
+        })
+    }
+    /// The scans in the order their first probe was sent.
+    pub fn scans(&self) -> &[CapturedScan] {
+        &self.scans
+    }
+    pub fn into_scans(self) -> Vec<CapturedScan> {
+        self.scans
+    }
+}
+
+// One direction of a TCP connection
+#[derive(Default)]
+struct Stream {
+    // the sequence number of the first payload byte
+    base: Option<u32>,
+    segments: Vec<(u32, Vec<u8>)>,
+    fin: bool,
+    rst: bool,
+}
+
+impl Stream {
+    fn push(&mut self, segment: Segment) {
+        self.fin |= segment.flags & FIN != 0;
+        self.rst |= segment.flags & RST != 0;
+        if segment.flags & SYN != 0 {
+            self.base = Some(segment.seq.wrapping_add(1));
+            return;
+        }
+        let base = *self.base.get_or_insert(segment.seq);
+        let offset = segment.seq.wrapping_sub(base);
+        // #Offsets past 2^31 are from before the base, a retransmission we can not place
+        if !segment.payload.is_empty() && offset < 1 << 31 {
+            self.segments.push((offset, segment.payload));
+        }
+    }
+    fn has_data(&self) -> bool {
+        !self.segments.is_empty()
+    }
+    // #The payload in sequence order as it was split up on the wire, up to the first gap
+    fn chunks(&self) -> Vec<&[u8]> {
+        let mut segments: Vec<&(u32, Vec<u8>)> = self.segments.iter().collect();
+        segments.sort_by_key(|(offset, _)| *offset);
+        let mut chunks = Vec::new();
+        let mut next = 0usize;
+        for (offset, payload) in segments {
+            let offset = *offset as usize;
+            if offset > next {
+                break;
+            }
+            // skip what a retransmission already delivered
+            if let Some(chunk) = payload.get(next - offset..).filter(|c| !c.is_empty()) {
+                next += chunk.len();
+                chunks.push(chunk);
+            }
+        }
+        chunks
+    }
+}
+
+struct Connection {
+    client: SocketAddr,
+    server: SocketAddr,
+    started: Duration,
+    from_client: Stream,
+    from_server: Stream,
+}
+
+// #TCP connections in the order they started, a new SYN on a used port pair starts another
+fn connections(segments: Vec<Segment>) -> Vec<Connection> {
+    let mut connections: Vec<Connection> = Vec::new();
+    let mut open: HashMap<(SocketAddr, SocketAddr), usize> = HashMap::new();
+    for segment in segments {
+        let found = open
+            .get(&(segment.src, segment.dst))
+            .or_else(|| open.get(&(segment.dst, segment.src)))
+            .copied();
+        let is_syn = segment.flags & (SYN | ACK) == SYN;
+        let index = match found {
+            Some(index) if !(is_syn && connections[index].from_client.has_data()) => index,
+            _ => {
+                // #Without the SYN, the side that sent a SYN-ACK or else the first packet is the server
+                let (client, server) = match segment.flags & (SYN | ACK) == SYN | ACK {
+                    true => (segment.dst, segment.src),
+                    false => (segment.src, segment.dst),
+                };
+                connections.push(Connection {
+                    client,
+                    server,
+                    started: segment.timestamp,
+                    from_client: Stream::default(),
+                    from_server: Stream::default(),
+                });
+                open.insert((client, server), connections.len() - 1);
+                connections.len() - 1
+            }
+        };
+        let connection = &mut connections[index];
+        match segment.src == connection.client {
+            true => connection.from_client.push(segment),
+            false => connection.from_server.push(segment),
+        }
+    }
+    connections
+}
+
+// #Probes found so far for one client, server and server name
+struct Scan {
+    client: IpAddr,
+    server: SocketAddr,
+    sni: Option<String>,
+    started: Duration,
+    probes: Vec<Option<(Part, ProbeResult)>>,
+}
+
+fn scans(connections: &[Connection]) -> Vec<CapturedScan> {
+    let set = ProbeSet::jarm();
+    let shapes = probe_shapes();
+    let mut scans: Vec<Scan> = Vec::new();
+    let mut open: HashMap<(IpAddr, SocketAddr, Option<String>), usize> = HashMap::new();
+    for connection in connections {
+        let hello = connection.from_client.chunks().concat();
+        let (shape, sni) = match hello_shape(&hello) {
+            Some(shape) => shape,
+            None => continue,
+        };
+        let index = match shapes.iter().position(|s| *s == shape) {
+            Some(index) => index,
+            None => continue,
+        };
+        let key = (connection.client.ip(), connection.server, sni.clone());
+        // #A probe the scan already has starts the next scan of the same server
+        let scan = match open.get(&key) {
+            Some(&scan) if scans[scan].probes[index].is_none() => scan,
+            _ => {
+                scans.push(Scan {
+                    client: connection.client.ip(),
+                    server: connection.server,
+                    sni,
+                    started: connection.started,
+                    probes: (0..set.probes().len()).map(|_| None).collect(),
+                });
+                open.insert(key, scans.len() - 1);
+                scans.len() - 1
+            }
+        };
+        scans[scan].probes[index] = Some(read_probe(&set.probes()[index].name, reply(connection)));
+    }
+    scans
+        .into_iter()
+        .map(|scan| {
+            let (parts, probes): (Vec<Part>, Vec<ProbeResult>) = scan
+                .probes
+                .into_iter()
+                .zip(set.probes())
+                .map(|(probe, spec)| {
+                    probe.unwrap_or_else(|| read_probe(&spec.name, Err(JarmError::NotCaptured)))
+                })
+                .unzip();
+            CapturedScan {
+                client: scan.client,
+                sni: scan.sni,
+                // #A crafted capture can date a packet past what SystemTime holds
+                timestamp: SystemTime::UNIX_EPOCH
+                    .checked_add(scan.started)
+                    .unwrap_or(SystemTime::UNIX_EPOCH),
+                result: ScanResult {
+                    addr: Some(scan.server),
+                    fingerprint: JarmFingerprint::from_parts(&parts),
+                    probe_set: set.name().to_string(),
+                    probes,
+                    certificate: leaf_certificate(&parts),
+                    handshake: None,
+                    matches: Vec::new(),
+                },
+            }
+        })
+        .collect()
+}
+
+// #The server's reply read segment by segment, stopping where a live scan would
+fn reply(connection: &Connection) -> Result<(Vec<u8>, usize), JarmError> {
+    let mut response = Response::default();
+    for chunk in connection.from_server.chunks() {
+        response.push(chunk);
+        if response.is_complete() {
+            break;
+        }
+    }
+    if !response.is_empty() {
+        return Ok((response.data(), response.len()));
+    }
+    Err(match &connection.from_server {
+        stream if stream.rst => JarmError::ConnectionReset,
+        stream if stream.fin => JarmError::ConnectionClosed,
+        _ => JarmError::ReadTimeout,
+    })
+}
+
+/// The shape of each JARM probe in order, see [`hello_shape`].
+pub(crate) fn probe_shapes() -> Vec<Vec<u8>> {
+    ProbeSet::jarm()
+        .probes()
+        .iter()
+        .filter_map(|p| {
+            hello_shape(&p.build_packet(None, &mut thread_rng())).map(|(shape, _)| shape)
+        })
+        .collect()
+}
+
+fn is_grease(value: u16) -> bool {
+    value >> 8 == value & 0xff && value & 0x0f == 0x0a
+}
+
+fn push_u16(shape: &mut Vec<u8>, value: u16) {
+    let value = if is_grease(value) { 0x0a0a } else { value };
+    shape.extend(value.to_be_bytes());
+}
+
+// #A ClientHello without what changes between packets of the same probe: the
+// random, the session id, GREASE values, the key share and the server name
+pub(crate) fn hello_shape(data: &[u8]) -> Option<(Vec<u8>, Option<String>)> {
+    parse_hello(data).ok()
+}
+
+fn parse_hello(data: &[u8]) -> Result<(Vec<u8>, Option<String>), JarmError> {
+    let mut shape = Vec::new();
+    let mut sni = None;
+    let mut record = Reader::new(data);
+    if record.u8()? != 22 {
+        return Err(JarmError::NonTls);
+    }
+    shape.extend(record.bytes(2)?);
+    let length = record.u16()? as usize;
+    let mut message = record.sub(length)?;
+    if message.u8()? != 1 {
+        return Err(JarmError::NonTls);
+    }
+    let length = message.u24()?;
+    let mut hello = message.sub(length)?;
+    shape.extend(hello.bytes(2)?);
+    hello.bytes(32)?;
+    let session_id = hello.u8()? as usize;
+    hello.bytes(session_id)?;
+    let length = hello.u16()? as usize;
+    let mut ciphers = hello.sub(length)?;
+    while !ciphers.is_empty() {
+        push_u16(&mut shape, ciphers.u16()?);
+    }
+    let length = hello.u8()? as usize;
+    shape.extend(hello.bytes(length)?);
+    if hello.is_empty() {
+        return Ok((shape, sni));
+    }
+    let length = hello.u16()? as usize;
+    let mut extensions = hello.sub(length)?;
+    while !extensions.is_empty() {
+        let kind = extensions.u16()?;
+        let length = extensions.u16()? as usize;
+        let mut body = extensions.sub(length)?;
+        match kind {
+            0x0000 => {
+                // server name list, name type and host name
+                body.bytes(3)?;
+                let length = body.u16()? as usize;
+                sni = Some(String::from_utf8_lossy(body.bytes(length)?).into_owned());
+            }
+            0x0033 => push_u16(&mut shape, kind),
+            0x002b => {
+                push_u16(&mut shape, kind);
+                shape.push(body.u8()?);
+                while !body.is_empty() {
+                    push_u16(&mut shape, body.u16()?);
+                }
+            }
+            _ => {
+                push_u16(&mut shape, kind);
+                shape.extend((length as u16).to_be_bytes());
+                shape.extend(body.bytes(length)?);
+            }
+        }
+    }
+    Ok((shape, sni))
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use time::format_description::well_known::Rfc3339;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
+use x509_parser::public_key::PublicKey;
+use x509_parser::time::ASN1Time;
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser, Certificate, ScanResult};
+
+    // The tls1_2_certificate hello of tests/golden/server_hellos.toml
+    fn leaf() -> Vec<u8> {
+        let corpus: toml::Table =
+            toml::from_str(include_str!("../tests/golden/server_hellos.toml")).unwrap();
+        let hello = corpus["hello"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["name"].as_str() == Some("tls1_2_certificate"))
+            .unwrap();
+        let data = hex::decode(hello["data"].as_str().unwrap()).unwrap();
+        parser::certificate_chain(&data).unwrap().remove(0)
+    }
+
+    #[test]
+    fn leaf_details() {
+        let cert = Certificate::from_der(&leaf()).unwrap();
+        assert_eq!(cert.subject, "C=US, O=Example, CN=example.com");
+        assert_eq!(cert.issuer, "C=US, O=jarm-rs, CN=jarm-rs test CA");
+        assert_eq!(cert.sans, ["example.com", "www.example.com", "127.0.0.1"]);
+        assert_eq!(cert.not_before, "2024-01-01T00:00:00Z");
+        assert_eq!(cert.not_after, "2034-01-01T00:00:00Z");
+        assert_eq!(cert.key_type, "EC");
+        assert_eq!(cert.key_bits, 256);
+        assert_eq!(cert.sha1.len(), 40);
+        assert_eq!(cert.sha256.len(), 64);
+        let json = serde_json::to_string(&cert).unwrap();
+        assert_eq!(serde_json::from_str::<Certificate>(&json).unwrap(), cert);
+        assert_eq!(Certificate::from_der(b"\x30\x00"), None);
+        // results saved before certificates were read still load
+        let result: ScanResult = serde_json::from_str(
+            r#"{"addr":null,"fingerprint":"00000000000000000000000000000000000000000000000000000000000000","probes":[]}"#,
+        )
+        .unwrap();
+        assert_eq!(result.certificate, None);
+    }
+}
+
+/// The leaf certificate a TLS 1.2 server sent in plaintext after its server hello.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Certificate {
+    /// Distinguished name, e.g. `C=US, O=Example, CN=example.com`
+    pub subject: String,
+    pub issuer: String,
+    /// DNS names and IP addresses of the subject alternative name extension
+    pub sans: Vec<String>,
+    /// Start of the validity period, RFC 3339 in UTC
+    pub not_before: String,
+    /// End of the validity period, RFC 3339 in UTC
+    pub not_after: String,
+    /// Public key algorithm: `RSA`, `EC`, `DSA`, `Ed25519`, `Ed448` or the OID of another
+    pub key_type: String,
+    /// Public key size in bits, the curve size for EC keys
+    pub key_bits: usize,
+    /// SHA-1 of the DER certificate as hex
+    pub sha1: String,
+    /// SHA-256 of the DER certificate as hex
+    pub sha256: String,
+}
+
+impl Certificate {
+    /// Parse a DER encoded X.509 certificate, `None` when it is not one.
+    pub fn from_der(der: &[u8]) -> Option<Certificate> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let sans = match cert.subject_alternative_name() {
+            Ok(Some(san)) => san
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    GeneralName::IPAddress(ip) => ip_address(ip),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let (key_type, key_bits) = public_key(&cert);
+        let validity = cert.validity();
+        Some(Certificate {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            sans,
+            not_before: rfc3339(validity.not_before),
+            not_after: rfc3339(validity.not_after),
+            key_type,
+            key_bits,
+            sha1: hex::encode(Sha1::digest(der)),
+            sha256: hex::encode(Sha256::digest(der)),
+        })
+    }
+}
+
+fn ip_address(bytes: &[u8]) -> Option<String> {
+    let ip = match bytes.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?),
+        16 => IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?),
+        _ => return None,
+    };
+    Some(ip.to_string())
+}
+
+fn rfc3339(time: ASN1Time) -> String {
+    time.to_datetime().format(&Rfc3339).unwrap_or_default()
+}
+
+// #The algorithm name and size of the subject's public key
+fn public_key(cert: &X509Certificate) -> (String, usize) {
+    let info = cert.public_key();
+    let key = &info.subject_public_key.data;
+    match info.parsed() {
+        // #Count the bits of the modulus past its leading zeros
+        Ok(PublicKey::RSA(rsa)) => {
+            let modulus = match rsa.modulus.iter().position(|b| *b != 0) {
+                Some(start) => &rsa.modulus[start..],
+                None => &[],
+            };
+            let bits = modulus
+                .first()
+                .map_or(0, |b| modulus.len() * 8 - b.leading_zeros() as usize);
+            ("RSA".to_string(), bits)
+        }
+        Ok(PublicKey::EC(ec)) => ("EC".to_string(), ec.key_size()),
+        Ok(PublicKey::DSA(y)) => ("DSA".to_string(), y.len() * 8),
+        _ => {
+            let oid = info.algorithm.algorithm.to_id_string();
+            let name = match oid.as_str() {
+                "1.3.101.112" => "Ed25519".to_string(),
+                "1.3.101.113" => "Ed448".to_string(),
+                _ => oid,
+            };
+            (name, key.len() * 8)
+        }
+    }
+}
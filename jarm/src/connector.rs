@@ -0,0 +1,434 @@
+use base64::Engine;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::hello_server;
+    use crate::{HttpProxy, JarmError, Scanner, Socks5Proxy};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::sync::mpsc;
+
+    fn relay(client: TcpStream, upstream: TcpStream) {
+        let (mut c1, mut u1) = (client.try_clone().unwrap(), upstream.try_clone().unwrap());
+        std::thread::spawn(move || std::io::copy(&mut c1, &mut u1));
+        let (mut c2, mut u2) = (client, upstream);
+        std::thread::spawn(move || std::io::copy(&mut u2, &mut c2));
+    }
+
+    // A SOCKS5 proxy that wants user:pass and sends every host to `upstream`
+    fn socks5_proxy(upstream: SocketAddr) -> (SocketAddr, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut s = stream.unwrap();
+                let mut buf = [0; 512];
+                s.read_exact(&mut buf[..2]).unwrap();
+                let methods = buf[1] as usize;
+                s.read_exact(&mut buf[..methods]).unwrap();
+                s.write_all(b"\x05\x02").unwrap();
+                s.read_exact(&mut buf[..2]).unwrap();
+                let mut user = vec![0; buf[1] as usize];
+                s.read_exact(&mut user).unwrap();
+                s.read_exact(&mut buf[..1]).unwrap();
+                let mut pass = vec![0; buf[0] as usize];
+                s.read_exact(&mut pass).unwrap();
+                if user != b"user" || pass != b"pass" {
+                    s.write_all(b"\x01\x01").unwrap();
+                    continue;
+                }
+                s.write_all(b"\x01\x00").unwrap();
+                s.read_exact(&mut buf[..5]).unwrap();
+                assert_eq!(buf[3], 3, "expected a domain name");
+                let mut host = vec![0; buf[4] as usize + 2];
+                s.read_exact(&mut host).unwrap();
+                host.truncate(host.len() - 2);
+                tx.send(String::from_utf8(host).unwrap()).unwrap();
+                s.write_all(b"\x05\x00\x00\x01\x7f\x00\x00\x01\x00\x00")
+                    .unwrap();
+                relay(s, TcpStream::connect(upstream).unwrap());
+            }
+        });
+        (addr, rx)
+    }
+
+    // An HTTP proxy that sends every CONNECT to `upstream`, reporting each request and
+    // where it came from
+    fn http_proxy(upstream: SocketAddr) -> (SocketAddr, mpsc::Receiver<(String, SocketAddr)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let s = stream.unwrap();
+                let mut reader = BufReader::new(s.try_clone().unwrap());
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                tx.send((request, s.peer_addr().unwrap())).unwrap();
+                let mut s = s;
+                s.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                    .unwrap();
+                relay(s, TcpStream::connect(upstream).unwrap());
+            }
+        });
+        (addr, rx)
+    }
+
+    #[test]
+    fn socks5_with_remote_dns() {
+        let upstream = hello_server();
+        let (proxy, hosts) = socks5_proxy(upstream);
+        let direct = Scanner::builder("127.0.0.1", upstream.port())
+            .build()
+            .unwrap()
+            .scan();
+        let scanner = Scanner::builder("only.resolvable.by.the.proxy", 443)
+            .connector(Socks5Proxy::new(proxy).auth("user", "pass"))
+            .build()
+            .unwrap();
+        assert_eq!(scanner.addr(), None);
+        let result = scanner.scan();
+        assert!(result.error().is_none());
+        assert_eq!(result.fingerprint, direct.fingerprint);
+        assert_eq!(hosts.try_iter().count(), 10);
+    }
+
+    #[test]
+    fn socks5_bad_credentials() {
+        let (proxy, _hosts) = socks5_proxy(hello_server());
+        let result = Scanner::builder("example.com", 443)
+            .connector(Socks5Proxy::new(proxy).auth("user", "wrong"))
+            .build()
+            .unwrap()
+            .scan();
+        assert!(matches!(result.error(), Some(JarmError::Io(_))));
+    }
+
+    #[test]
+    fn http_connect() {
+        let upstream = hello_server();
+        let (proxy, requests) = http_proxy(upstream);
+        let result = Scanner::builder("example.com", 8443)
+            .connector(HttpProxy::new(proxy).auth("user", "pass"))
+            .build()
+            .unwrap()
+            .scan();
+        assert!(result.error().is_none());
+        let (request, _) = requests.recv().unwrap();
+        assert!(request.starts_with("CONNECT example.com:8443 HTTP/1.1\r\n"));
+        assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+    }
+
+    #[test]
+    fn bind_with_proxy() {
+        let (proxy, requests) = http_proxy(hello_server());
+        let result = Scanner::builder("example.com", 443)
+            .connector(HttpProxy::new(proxy))
+            .bind("127.0.0.2:0".parse().unwrap())
+            .build()
+            .unwrap()
+            .scan();
+        assert!(result.error().is_none());
+        // the proxy sees every probe come from the bind address
+        let peers: Vec<SocketAddr> = requests.try_iter().map(|(_, peer)| peer).collect();
+        assert_eq!(peers.len(), 10);
+        assert!(peers
+            .iter()
+            .all(|p| p.ip() == std::net::Ipv4Addr::new(127, 0, 0, 2)));
+    }
+}
+
+/// Where a probe connects to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Addr(SocketAddr),
+    /// A host name left for the connector to resolve
+    Host(String, u16),
+}
+
+impl Target {
+    pub fn addr(&self) -> Option<SocketAddr> {
+        match self {
+            Target::Addr(addr) => Some(*addr),
+            Target::Host(..) => None,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Addr(addr) => write!(f, "{}", addr),
+            Target::Host(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// Opens the TCP connection each probe is sent over, e.g. through a proxy.
+pub trait Connector: fmt::Debug + Send + Sync {
+    /// `local` is the [`ScannerBuilder::bind`](crate::ScannerBuilder::bind)
+    /// address, to connect from when it is set.
+    fn connect(
+        &self,
+        target: &Target,
+        local: Option<SocketAddr>,
+        timeout: Duration,
+    ) -> io::Result<TcpStream>;
+    /// Host names are passed on to the connector instead of being resolved locally.
+    fn remote_dns(&self) -> bool {
+        false
+    }
+}
+
+// Run a proxy handshake with `timeout` on every read and write
+fn with_timeout<T>(
+    stream: &mut TcpStream,
+    timeout: Duration,
+    handshake: impl FnOnce(&mut TcpStream) -> io::Result<T>,
+) -> io::Result<T> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let result = handshake(stream)?;
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    Ok(result)
+}
+
+fn proxy_error(message: impl Into<String>) -> io::Error {
+    io::Error::other(message.into())
+}
+
+/// Tunnels probes through a SOCKS5 proxy.
+#[derive(Debug, Clone)]
+pub struct Socks5Proxy {
+    proxy: SocketAddr,
+    auth: Option<(String, String)>,
+    remote_dns: bool,
+}
+
+impl Socks5Proxy {
+    /// A proxy that resolves host names itself, as Tor needs.
+    pub fn new(proxy: SocketAddr) -> Self {
+        Socks5Proxy {
+            proxy,
+            auth: None,
+            remote_dns: true,
+        }
+    }
+    /// Username and password authentication.
+    pub fn auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some((username.into(), password.into()));
+        self
+    }
+    /// Resolve host names locally and send the proxy addresses instead.
+    pub fn local_dns(mut self) -> Self {
+        self.remote_dns = false;
+        self
+    }
+    fn handshake(&self, stream: &mut TcpStream, target: &Target) -> io::Result<()> {
+        let method = if self.auth.is_some() { 0x02 } else { 0x00 };
+        stream.write_all(&[0x05, 0x01, method])?;
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply)?;
+        if reply[0] != 0x05 || reply[1] != method {
+            return Err(proxy_error(
+                "socks5 proxy refused the authentication method",
+            ));
+        }
+        if let Some((username, password)) = &self.auth {
+            if username.len() > 255 || password.len() > 255 {
+                return Err(proxy_error("socks5 username or password too long"));
+            }
+            let mut request = vec![0x01, username.len() as u8];
+            request.extend(username.as_bytes());
+            request.push(password.len() as u8);
+            request.extend(password.as_bytes());
+            stream.write_all(&request)?;
+            stream.read_exact(&mut reply)?;
+            if reply[1] != 0x00 {
+                return Err(proxy_error("socks5 authentication failed"));
+            }
+        }
+
+        let mut request = vec![0x05, 0x01, 0x00];
+        let port = match target {
+            Target::Addr(addr) => {
+                match addr.ip() {
+                    IpAddr::V4(ip) => {
+                        request.push(0x01);
+                        request.extend(ip.octets());
+                    }
+                    IpAddr::V6(ip) => {
+                        request.push(0x04);
+                        request.extend(ip.octets());
+                    }
+                }
+                addr.port()
+            }
+            Target::Host(host, port) => {
+                if host.len() > 255 {
+                    return Err(proxy_error("host name too long for socks5"));
+                }
+                request.push(0x03);
+                request.push(host.len() as u8);
+                request.extend(host.as_bytes());
+                *port
+            }
+        };
+        request.extend(port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply)?;
+        match reply[1] {
+            0x00 => {}
+            0x05 => return Err(io::ErrorKind::ConnectionRefused.into()),
+            0x06 => return Err(io::ErrorKind::TimedOut.into()),
+            code => {
+                return Err(proxy_error(format!(
+                    "socks5 proxy failed to connect, reply {}",
+                    code
+                )))
+            }
+        }
+        // Skip the bound address
+        let bound = match reply[3] {
+            0x01 => 4,
+            0x04 => 16,
+            0x03 => {
+                let mut length = [0; 1];
+                stream.read_exact(&mut length)?;
+                length[0] as usize
+            }
+            _ => return Err(proxy_error("socks5 proxy sent an unknown address type")),
+        };
+        let mut skip = vec![0; bound + 2];
+        stream.read_exact(&mut skip)?;
+        Ok(())
+    }
+}
+
+impl Connector for Socks5Proxy {
+    fn connect(
+        &self,
+        target: &Target,
+        local: Option<SocketAddr>,
+        timeout: Duration,
+    ) -> io::Result<TcpStream> {
+        let mut stream = connect_from(self.proxy, local, timeout)?;
+        with_timeout(&mut stream, timeout, |s| self.handshake(s, target))?;
+        Ok(stream)
+    }
+    fn remote_dns(&self) -> bool {
+        self.remote_dns
+    }
+}
+
+/// Tunnels probes through an HTTP proxy with `CONNECT`.
+#[derive(Debug, Clone)]
+pub struct HttpProxy {
+    proxy: SocketAddr,
+    auth: Option<(String, String)>,
+}
+
+impl HttpProxy {
+    pub fn new(proxy: SocketAddr) -> Self {
+        HttpProxy { proxy, auth: None }
+    }
+    /// Basic authentication.
+    pub fn auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some((username.into(), password.into()));
+        self
+    }
+    fn handshake(&self, stream: &mut TcpStream, target: &Target) -> io::Result<()> {
+        let authority = match target {
+            Target::Addr(SocketAddr::V6(addr)) => format!("[{}]:{}", addr.ip(), addr.port()),
+            target => target.to_string(),
+        };
+        let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+        if let Some((username, password)) = &self.auth {
+            let credentials = base64::engine::general_purpose::STANDARD
+                .encode(format!("{}:{}", username, password));
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        // Byte by byte, so nothing the server sends after the headers is lost
+        let mut response = Vec::new();
+        let mut byte = [0; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() > 8192 {
+                return Err(proxy_error("http proxy response too long"));
+            }
+            stream.read_exact(&mut byte)?;
+            response.push(byte[0]);
+        }
+        let response = String::from_utf8_lossy(&response);
+        let status = response.split_whitespace().nth(1).unwrap_or_default();
+        if status != "200" {
+            return Err(proxy_error(format!(
+                "http proxy refused to connect: {}",
+                response.lines().next().unwrap_or_default()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Connector for HttpProxy {
+    fn connect(
+        &self,
+        target: &Target,
+        local: Option<SocketAddr>,
+        timeout: Duration,
+    ) -> io::Result<TcpStream> {
+        let mut stream = connect_from(self.proxy, local, timeout)?;
+        with_timeout(&mut stream, timeout, |s| self.handshake(s, target))?;
+        Ok(stream)
+    }
+    fn remote_dns(&self) -> bool {
+        true
+    }
+}
+
+// Connect to `addr`, from `local` when it is set
+pub(crate) fn connect_from(
+    addr: SocketAddr,
+    local: Option<SocketAddr>,
+    timeout: Duration,
+) -> io::Result<TcpStream> {
+    let local = match local {
+        None => return TcpStream::connect_timeout(&addr, timeout),
+        Some(local) => local,
+    };
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&local.into())?;
+    socket.connect_timeout(&addr.into(), timeout)?;
+    Ok(socket.into())
+}
+
+// Resolve a target the connector left to us
+pub(crate) fn resolve(target: &Target) -> io::Result<SocketAddr> {
+    match target {
+        Target::Addr(addr) => Ok(*addr),
+        Target::Host(host, port) => (host.as_str(), *port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::ErrorKind::AddrNotAvailable.into()),
+    }
+}
@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+use crate::probe::JARM_NAME;
+use crate::{JarmFingerprint, ScanResult, Similarity};
+
+#[cfg(test)]
+mod tests {
+    use crate::{DatabaseError, FingerprintDb, JarmFingerprint, ScanResult};
+    use std::str::FromStr;
+
+    const COBALT_STRIKE: &str = "07d14d16d21d21d07c42d41d00041d24a458a375eef0c576d23a7bab9a9fb1";
+    const NGINX: &str = "2ad2ad0002ad2ad00042d42d00000069d641f34fe76acdc05c40262f8815e5";
+
+    fn fingerprint(s: &str) -> JarmFingerprint {
+        JarmFingerprint::from_str(s).unwrap()
+    }
+
+    fn scan_result(s: &str) -> ScanResult {
+        ScanResult {
+            addr: None,
+            fingerprint: fingerprint(s),
+            probe_set: "jarm".to_string(),
+            probes: Vec::new(),
+            certificate: None,
+            handshake: None,
+            matches: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn bundled() {
+        let db = FingerprintDb::bundled();
+        assert!(!db.entries().is_empty());
+        let matches = db.lookup(&fingerprint(COBALT_STRIKE));
+        assert_eq!(matches[0].label, "Cobalt Strike default");
+        assert_eq!(matches[0].confidence, 50);
+        assert!(db.lookup(&JarmFingerprint::zero()).is_empty());
+    }
+
+    #[test]
+    fn formats() {
+        let toml = format!(
+            "[[fingerprint]]\njarm = \"{}\"\nlabel = \"nginx + OpenSSL 1.1.1\"\nconfidence = 60\n",
+            NGINX
+        );
+        let json = format!(
+            r#"{{"fingerprint": [{{"jarm": "{}", "label": "nginx + OpenSSL 1.1.1", "confidence": 60}}]}}"#,
+            NGINX
+        );
+        let csv = format!(
+            "jarm,label,confidence\n{},nginx + OpenSSL 1.1.1,60\n",
+            NGINX
+        );
+        for db in [
+            FingerprintDb::from_toml(&toml).unwrap(),
+            FingerprintDb::from_json(&json).unwrap(),
+            FingerprintDb::from_csv(&csv).unwrap(),
+        ] {
+            assert_eq!(db.entries().len(), 1);
+            let matches = db.lookup(&fingerprint(NGINX));
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].label, "nginx + OpenSSL 1.1.1");
+            assert_eq!(matches[0].confidence, 60);
+        }
+        // confidence defaults to 100, a bare json array works too
+        let db = FingerprintDb::from_json(&format!(r#"[{{"jarm": "{}", "label": "x"}}]"#, NGINX))
+            .unwrap();
+        assert_eq!(db.entries()[0].confidence, 100);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            FingerprintDb::from_csv("jarm,label\nnot-a-fingerprint,x\n"),
+            Err(DatabaseError::Parse(_))
+        ));
+        assert!(matches!(
+            FingerprintDb::from_toml("[[fingerprint]]\njarm = \"00\"\nlabel = \"x\"\n"),
+            Err(DatabaseError::Parse(_))
+        ));
+        assert!(matches!(
+            FingerprintDb::from_toml(&format!(
+                "[[fingerprint]]\njarm = \"{}\"\nlabel = \"x\"\nconfidence = 101\n",
+                NGINX
+            )),
+            Err(DatabaseError::Parse(_))
+        ));
+        assert!(matches!(
+            FingerprintDb::load("fingerprints.yaml"),
+            Err(DatabaseError::UnknownFormat(_))
+        ));
+    }
+
+    #[test]
+    fn nearest() {
+        let db = FingerprintDb::bundled();
+        // Cobalt Strike with one probe changed and another extension hash
+        let mut close =
+            scan_result("07d14d16d21d21d07c42d41d00042d24a458a375eef0c576d23a7bab9a9fb2");
+        let neighbours = db.nearest(&close, 2);
+        assert_eq!(neighbours.len(), 2);
+        assert_eq!(neighbours[0].entry.label, "Cobalt Strike default");
+        assert_eq!(neighbours[0].similarity.differing_probes, [9]);
+        assert!(!neighbours[0].similarity.extension_hash_match);
+        assert!(neighbours[0].similarity.score > neighbours[1].similarity.score);
+        assert!(db.nearest(&close, 0).is_empty());
+        // the same characters from other probes are not close to anything known
+        close.probe_set = "extra".to_string();
+        assert!(db.nearest(&close, 2).is_empty());
+    }
+
+    #[test]
+    fn merge_and_annotate() {
+        let mut db = FingerprintDb::bundled();
+        db.extend(
+            FingerprintDb::from_csv(&format!("jarm,label\n{},Lab C2\n", COBALT_STRIKE)).unwrap(),
+        );
+        let mut result = scan_result(COBALT_STRIKE);
+        db.annotate(&mut result);
+        let labels: Vec<&str> = result.matches.iter().map(|m| m.label.as_str()).collect();
+        // the most confident match first
+        assert_eq!(labels, ["Lab C2", "Cobalt Strike default"]);
+        // the same characters from other probes mean something else
+        result.probe_set = "extra".to_string();
+        db.annotate(&mut result);
+        assert!(result.matches.is_empty());
+    }
+}
+
+const BUNDLED: &str = include_str!("../data/fingerprints.toml");
+
+/// A known fingerprint and what it means.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DbEntry {
+    pub jarm: JarmFingerprint,
+    /// What the fingerprint identifies, e.g. `Cobalt Strike default`
+    pub label: String,
+    /// How sure a match is, in percent
+    #[serde(default = "full_confidence")]
+    pub confidence: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+fn full_confidence() -> u8 {
+    100
+}
+
+/// A label a result matched in a [`FingerprintDb`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Match {
+    pub label: String,
+    /// How sure the match is, in percent
+    pub confidence: u8,
+    /// The fingerprint of the entry that matched
+    pub jarm: JarmFingerprint,
+}
+
+/// An entry close to a fingerprint, from [`FingerprintDb::nearest`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Neighbour {
+    pub entry: DbEntry,
+    pub similarity: Similarity,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    Io(String),
+    /// The file is not valid TOML, JSON or CSV, or an entry is invalid
+    Parse(String),
+    /// The file extension is not `.toml`, `.json` or `.csv`
+    UnknownFormat(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::Io(e) => write!(f, "io error: {}", e),
+            DatabaseError::Parse(e) => write!(f, "invalid fingerprint database: {}", e),
+            DatabaseError::UnknownFormat(path) => {
+                write!(f, "{} is not a .toml, .json or .csv file", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+// The layout of the TOML and JSON files
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum File {
+    Table { fingerprint: Vec<DbEntry> },
+    List(Vec<DbEntry>),
+}
+
+/// Known fingerprints mapped to labels.
+///
+/// ```
+/// use jarm::{FingerprintDb, JarmFingerprint};
+/// use std::str::FromStr;
+///
+/// let db = FingerprintDb::bundled();
+/// let fp = JarmFingerprint::from_str("07d14d16d21d21d00042d43d000000aa99ce74e2c6d013c745aa52b5cc042d").unwrap();
+/// assert_eq!(db.lookup(&fp)[0].label, "Metasploit");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FingerprintDb {
+    entries: Vec<DbEntry>,
+}
+
+impl FingerprintDb {
+    pub fn new() -> Self {
+        FingerprintDb::default()
+    }
+    /// The database that ships with the crate.
+    pub fn bundled() -> Self {
+        FingerprintDb::from_toml(BUNDLED).expect("bundled fingerprint database is valid")
+    }
+    /// Load a `.toml`, `.json` or `.csv` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DatabaseError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let parse = match extension.as_deref() {
+            Some("toml") => FingerprintDb::from_toml,
+            Some("json") => FingerprintDb::from_json,
+            Some("csv") => FingerprintDb::from_csv,
+            _ => return Err(DatabaseError::UnknownFormat(path.display().to_string())),
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| DatabaseError::Io(e.to_string()))?;
+        parse(&text)
+    }
+    /// `[[fingerprint]]` tables with `jarm`, `label` and optional `confidence` and `notes`.
+    pub fn from_toml(text: &str) -> Result<Self, DatabaseError> {
+        let file: File = toml::from_str(text).map_err(|e| DatabaseError::Parse(e.to_string()))?;
+        FingerprintDb::from_file(file)
+    }
+    /// `{"fingerprint": [...]}` or a bare array of entries.
+    pub fn from_json(text: &str) -> Result<Self, DatabaseError> {
+        let file: File =
+            serde_json::from_str(text).map_err(|e| DatabaseError::Parse(e.to_string()))?;
+        FingerprintDb::from_file(file)
+    }
+    /// A header row naming the `jarm`, `label`, `confidence` and `notes` columns.
+    pub fn from_csv(text: &str) -> Result<Self, DatabaseError> {
+        let mut entries = Vec::new();
+        for entry in csv::Reader::from_reader(text.as_bytes()).deserialize() {
+            entries.push(entry.map_err(|e| DatabaseError::Parse(e.to_string()))?);
+        }
+        FingerprintDb::from_entries(entries)
+    }
+    fn from_file(file: File) -> Result<Self, DatabaseError> {
+        match file {
+            File::Table { fingerprint } => FingerprintDb::from_entries(fingerprint),
+            File::List(entries) => FingerprintDb::from_entries(entries),
+        }
+    }
+    fn from_entries(entries: Vec<DbEntry>) -> Result<Self, DatabaseError> {
+        if let Some(entry) = entries.iter().find(|e| e.confidence > 100) {
+            return Err(DatabaseError::Parse(format!(
+                "confidence of {} is over 100",
+                entry.label
+            )));
+        }
+        Ok(FingerprintDb { entries })
+    }
+    pub fn entries(&self) -> &[DbEntry] {
+        &self.entries
+    }
+    pub fn insert(&mut self, entry: DbEntry) {
+        self.entries.push(entry);
+    }
+    /// Add the entries of `other`, e.g. a user database on top of the bundled one.
+    pub fn extend(&mut self, other: FingerprintDb) {
+        self.entries.extend(other.entries);
+    }
+    /// Every entry with exactly this fingerprint, the most confident first.
+    pub fn lookup(&self, fingerprint: &JarmFingerprint) -> Vec<Match> {
+        if fingerprint.is_zero() {
+            return Vec::new();
+        }
+        let mut matches: Vec<Match> = self
+            .entries
+            .iter()
+            .filter(|e| &e.jarm == fingerprint)
+            .map(|e| Match {
+                label: e.label.clone(),
+                confidence: e.confidence,
+                jarm: e.jarm.clone(),
+            })
+            .collect();
+        matches.sort_by_key(|m| std::cmp::Reverse(m.confidence));
+        matches
+    }
+    /// The `k` entries most like the fingerprint of `result`, by score and then
+    /// by a matching extension hash, none for results of another probe set.
+    pub fn nearest(&self, result: &ScanResult, k: usize) -> Vec<Neighbour> {
+        if result.probe_set != JARM_NAME {
+            return Vec::new();
+        }
+        let mut neighbours: Vec<Neighbour> = self
+            .entries
+            .iter()
+            .map(|entry| Neighbour {
+                similarity: result.fingerprint.compare(&entry.jarm),
+                entry: entry.clone(),
+            })
+            .collect();
+        neighbours.sort_by(|a, b| {
+            let key = |n: &Neighbour| (n.similarity.score, n.similarity.extension_hash_match);
+            key(b)
+                .partial_cmp(&key(a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        neighbours.truncate(k);
+        neighbours
+    }
+    /// Fill in [`ScanResult::matches`], left empty for results of another probe set.
+    pub fn annotate(&self, result: &mut ScanResult) {
+        if result.probe_set != JARM_NAME {
+            result.matches.clear();
+            return;
+        }
+        result.matches = self.lookup(&result.fingerprint);
+    }
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+
+/// Why a scan, or a single probe of a scan, did not get a server hello.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum JarmError {
+    /// The host name did not resolve to any address
+    Resolve(String),
+    ConnectRefused,
+    ConnectTimeout,
+    ReadTimeout,
+    ConnectionReset,
+    /// The server closed the connection without sending anything
+    ConnectionClosed,
+    /// The server answered with a TLS alert record
+    Alert {
+        level: u8,
+        description: u8,
+    },
+    MalformedServerHello,
+    /// The server answered with something that is not a TLS record
+    NonTls,
+    /// The server refused the STARTTLS upgrade, with the first line of its reply
+    StartTls(String),
+    /// A packet capture has no connection for this probe
+    NotCaptured,
+    /// The full handshake after the probes failed, with the TLS stack's reason
+    Tls(String),
+    Io(String),
+}
+
+impl JarmError {
+    pub(crate) fn connect(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::ConnectionRefused => JarmError::ConnectRefused,
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => JarmError::ConnectTimeout,
+            _ => JarmError::Io(e.to_string()),
+        }
+    }
+    pub(crate) fn read(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => JarmError::ReadTimeout,
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => JarmError::ConnectionReset,
+            _ => JarmError::Io(e.to_string()),
+        }
+    }
+    /// The snake case name of the variant, the same as the serialized `kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JarmError::Resolve(_) => "resolve",
+            JarmError::ConnectRefused => "connect_refused",
+            JarmError::ConnectTimeout => "connect_timeout",
+            JarmError::ReadTimeout => "read_timeout",
+            JarmError::ConnectionReset => "connection_reset",
+            JarmError::ConnectionClosed => "connection_closed",
+            JarmError::Alert { .. } => "alert",
+            JarmError::MalformedServerHello => "malformed_server_hello",
+            JarmError::NonTls => "non_tls",
+            JarmError::StartTls(_) => "start_tls",
+            JarmError::NotCaptured => "not_captured",
+            JarmError::Tls(_) => "tls",
+            JarmError::Io(_) => "io",
+        }
+    }
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, JarmError::ConnectTimeout | JarmError::ReadTimeout)
+    }
+    // #Errors that may go away when the probe is sent again
+    pub(crate) fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            JarmError::ConnectTimeout
+                | JarmError::ReadTimeout
+                | JarmError::ConnectionReset
+                | JarmError::ConnectionClosed
+                | JarmError::Io(_)
+        )
+    }
+}
+
+impl fmt::Display for JarmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JarmError::Resolve(host) => write!(f, "failed to resolve {}", host),
+            JarmError::ConnectRefused => f.write_str("connection refused"),
+            JarmError::ConnectTimeout => f.write_str("connect timed out"),
+            JarmError::ReadTimeout => f.write_str("read timed out"),
+            JarmError::ConnectionReset => f.write_str("connection reset"),
+            JarmError::ConnectionClosed => f.write_str("connection closed without a response"),
+            JarmError::Alert { level, description } => {
+                write!(f, "tls alert level {} description {}", level, description)
+            }
+            JarmError::MalformedServerHello => f.write_str("malformed server hello"),
+            JarmError::NonTls => f.write_str("response is not tls"),
+            JarmError::StartTls(reply) => write!(f, "starttls refused: {}", reply),
+            JarmError::NotCaptured => f.write_str("probe not in the capture"),
+            JarmError::Tls(e) => write!(f, "tls handshake failed: {}", e),
+            JarmError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JarmError {}
@@ -0,0 +1,288 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Part;
+
+#[cfg(test)]
+mod tests {
+    use crate::{FingerprintError, JarmFingerprint};
+    use std::str::FromStr;
+
+    const SALESFORCE: &str = "2ad2ad0002ad2ad00042d42d00000069d641f34fe76acdc05c40262f8815e5";
+
+    #[test]
+    fn parse_and_accessors() {
+        let fp = JarmFingerprint::from_str(SALESFORCE).unwrap();
+        assert_eq!(fp.to_string(), SALESFORCE);
+        assert_eq!(fp.cipher_versions(), "2ad2ad0002ad2ad00042d42d000000");
+        assert_eq!(fp.extension_hash(), "69d641f34fe76acdc05c40262f8815e5");
+        assert_eq!(fp.probe(0), Some("2ad"));
+        assert_eq!(fp.probe(2), Some("000"));
+        assert_eq!(fp.probe(10), None);
+        assert_eq!(fp.probes().count(), 10);
+        assert!(!fp.is_zero());
+        assert_eq!(fp, SALESFORCE);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            JarmFingerprint::from_str("2ad"),
+            Err(FingerprintError::Length(3))
+        );
+        let upper = SALESFORCE.replace('d', "D");
+        assert_eq!(
+            JarmFingerprint::from_str(&upper),
+            Err(FingerprintError::Character('D'))
+        );
+        // a custom probe set of two probes, a triplet short of three
+        let two = JarmFingerprint::from_str(&SALESFORCE[24..]).unwrap();
+        assert_eq!(two.probes().collect::<Vec<_>>(), ["000", "000"]);
+        assert_eq!(two.extension_hash(), "69d641f34fe76acdc05c40262f8815e5");
+        assert_eq!(
+            JarmFingerprint::from_str(&SALESFORCE[25..]),
+            Err(FingerprintError::Length(37))
+        );
+    }
+
+    #[test]
+    fn compare() {
+        let a = JarmFingerprint::from_str(SALESFORCE).unwrap();
+        let same = a.compare(&a);
+        assert_eq!(same.score, 1.0);
+        assert!(same.is_identical());
+
+        // probe 1 picks another cipher, probe 9 another version, and a new extension hash
+        let b = JarmFingerprint::from_str(
+            "2ad2bd0002ad2ad00042d42d00000169d641f34fe76acdc05c40262f8815e6",
+        )
+        .unwrap();
+        let similarity = a.compare(&b);
+        assert_eq!(similarity.differing_probes, [1, 9]);
+        assert_eq!(similarity.cipher_matches, 9);
+        assert_eq!(similarity.version_matches, 9);
+        assert_eq!(similarity.score, 27.0 / 30.0);
+        assert!(!similarity.extension_hash_match);
+        assert!(!similarity.is_identical());
+        assert_eq!(b.compare(&a), similarity);
+    }
+
+    #[test]
+    fn zero() {
+        let fp = JarmFingerprint::zero();
+        assert!(fp.is_zero());
+        assert_eq!(fp.as_str(), "0".repeat(62));
+        assert_eq!(JarmFingerprint::from_str(&"0".repeat(62)).unwrap(), fp);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let fp = JarmFingerprint::from_str(SALESFORCE).unwrap();
+        let json = serde_json::to_string(&fp).unwrap();
+        assert_eq!(json, format!("\"{}\"", SALESFORCE));
+        let back: JarmFingerprint = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, fp);
+        assert!(serde_json::from_str::<JarmFingerprint>("\"xyz\"").is_err());
+    }
+}
+
+// 10 probes * (2 cipher chars + 1 version char)
+const CIPHER_VERSIONS_LEN: usize = 30;
+// truncated sha256 of the alpn and extension lists
+const EXTENSION_HASH_LEN: usize = 32;
+const FINGERPRINT_LEN: usize = CIPHER_VERSIONS_LEN + EXTENSION_HASH_LEN;
+
+/// A 62 character JARM fingerprint.
+///
+/// The first 30 characters are ten cipher+version triplets, one per probe,
+/// the last 32 characters are a truncated SHA-256 of the ALPN and extensions
+/// the server answered with. A custom [`ProbeSet`](crate::ProbeSet) makes
+/// one triplet per probe instead of ten.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JarmFingerprint(String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FingerprintError {
+    /// The fingerprint is not 32 characters plus 3 per probe long, 62 for JARM
+    Length(usize),
+    /// The fingerprint contains something other than lowercase hex
+    Character(char),
+}
+
+impl fmt::Display for FingerprintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FingerprintError::Length(len) => write!(
+                f,
+                "jarm fingerprint must be {} characters, or {} plus 3 per probe, got {}",
+                FINGERPRINT_LEN, EXTENSION_HASH_LEN, len
+            ),
+            FingerprintError::Character(c) => {
+                write!(f, "invalid character {:?} in jarm fingerprint", c)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FingerprintError {}
+
+impl JarmFingerprint {
+    /// The fingerprint of a server that never answered with TLS.
+    pub fn zero() -> Self {
+        JarmFingerprint("0".repeat(FINGERPRINT_LEN))
+    }
+    pub fn is_zero(&self) -> bool {
+        self.0.bytes().all(|b| b == b'0')
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    /// The cipher and version part, 3 characters per probe, 30 for JARM.
+    pub fn cipher_versions(&self) -> &str {
+        &self.0[..self.0.len() - EXTENSION_HASH_LEN]
+    }
+    /// The 32 character truncated SHA-256 of the ALPN and extensions.
+    pub fn extension_hash(&self) -> &str {
+        &self.0[self.0.len() - EXTENSION_HASH_LEN..]
+    }
+    /// The cipher+version triplet of the probe at `index`.
+    pub fn probe(&self, index: usize) -> Option<&str> {
+        let start = index * 3;
+        if start + 3 > self.cipher_versions().len() {
+            return None;
+        }
+        Some(&self.0[start..start + 3])
+    }
+    /// The cipher+version triplets in probe order, ten for JARM.
+    pub fn probes(&self) -> impl Iterator<Item = &str> {
+        (0..self.cipher_versions().len() / 3).filter_map(move |i| self.probe(i))
+    }
+    /// Compare probe by probe, for servers that differ in only a few probes.
+    pub fn compare(&self, other: &JarmFingerprint) -> Similarity {
+        let mut similarity = Similarity {
+            score: 0.0,
+            cipher_matches: 0,
+            version_matches: 0,
+            differing_probes: Vec::new(),
+            extension_hash_match: self.extension_hash() == other.extension_hash(),
+        };
+        for (index, (a, b)) in self.probes().zip(other.probes()).enumerate() {
+            let cipher = a[..2] == b[..2];
+            let version = a[2..] == b[2..];
+            similarity.cipher_matches += cipher as usize;
+            similarity.version_matches += version as usize;
+            if !(cipher && version) {
+                similarity.differing_probes.push(index);
+            }
+        }
+        // #Each agreeing cipher is worth its 2 characters, each agreeing version 1
+        let agreeing = similarity.cipher_matches * 2 + similarity.version_matches;
+        let len = self
+            .cipher_versions()
+            .len()
+            .max(other.cipher_versions().len());
+        similarity.score = agreeing as f64 / len as f64;
+        similarity
+    }
+    pub(crate) fn from_parts(parts: &[Part]) -> Self {
+        let mut fuzzy_hash = String::new();
+        let mut apln_and_ext = String::new();
+        for part in parts {
+            fuzzy_hash.push_str(&part.get_cipher());
+            fuzzy_hash.push(part.get_version());
+            apln_and_ext.push_str(&part.get_extensions_version());
+            apln_and_ext.push_str(&part.get_extensions_fingerprint());
+        }
+        // #If no probe got a server hello, the fingerprint is all zeros
+        if parts.iter().all(Part::is_empty) {
+            return JarmFingerprint("0".repeat(parts.len() * 3 + EXTENSION_HASH_LEN));
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(apln_and_ext.into_bytes());
+        let sha256 = hex::encode(hasher.finalize());
+        fuzzy_hash.push_str(&sha256[..EXTENSION_HASH_LEN]);
+        JarmFingerprint(fuzzy_hash)
+    }
+}
+
+/// How alike two fingerprints are, from [`JarmFingerprint::compare`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Similarity {
+    /// Share of the cipher and version characters that agree, from 0 to 1
+    pub score: f64,
+    /// Probes that selected the same cipher
+    pub cipher_matches: usize,
+    /// Probes that got the same version
+    pub version_matches: usize,
+    /// Indexes of the probes whose cipher or version differ
+    pub differing_probes: Vec<usize>,
+    /// The ALPN and extension hashes are the same
+    pub extension_hash_match: bool,
+}
+
+impl Similarity {
+    /// Every probe agrees and so do the extension hashes.
+    pub fn is_identical(&self) -> bool {
+        self.differing_probes.is_empty() && self.extension_hash_match
+    }
+}
+
+impl FromStr for JarmFingerprint {
+    type Err = FingerprintError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(c) = s.chars().find(|c| !matches!(c, '0'..='9' | 'a'..='f')) {
+            return Err(FingerprintError::Character(c));
+        }
+        let probes = s.len().saturating_sub(EXTENSION_HASH_LEN);
+        if probes == 0 || !probes.is_multiple_of(3) {
+            return Err(FingerprintError::Length(s.len()));
+        }
+        Ok(JarmFingerprint(s.to_string()))
+    }
+}
+
+impl fmt::Display for JarmFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for JarmFingerprint {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<JarmFingerprint> for String {
+    fn from(fp: JarmFingerprint) -> Self {
+        fp.0
+    }
+}
+
+impl PartialEq<str> for JarmFingerprint {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for JarmFingerprint {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Serialize for JarmFingerprint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for JarmFingerprint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        JarmFingerprint::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+
+use crate::builder::Config;
+use crate::connector::Target;
+use crate::ja4::truncated_hash;
+use crate::{Certificate, JarmError};
+
+#[cfg(test)]
+mod tests {
+    use crate::HttpResponse;
+
+    #[test]
+    fn parse_response() {
+        let response = HttpResponse::parse(
+            b"HTTP/1.1 301 Moved Permanently\r\nServer: nginx\r\nLocation: https://example.com/\r\nSet-Cookie: sid=1; Path=/\r\nSet-Cookie: lang=en\r\nContent-Length: 0\r\n\r\nbody",
+        )
+        .unwrap();
+        assert_eq!(response.status, 301);
+        assert_eq!(response.server.as_deref(), Some("nginx"));
+        assert_eq!(
+            response.headers,
+            [
+                "Server",
+                "Location",
+                "Set-Cookie",
+                "Set-Cookie",
+                "Content-Length"
+            ]
+        );
+        // Server,Location,Content-Length then lang,sid and lang=en,sid=1
+        assert_eq!(
+            response.ja4h,
+            "11301c03_7cd283481512_22090eb1cea3_91c347b4c9c6"
+        );
+        let http2 = HttpResponse::parse(b"HTTP/2 200\r\ncontent-type: text/html\r\n\r\n").unwrap();
+        assert_eq!(http2.server, None);
+        assert_eq!(
+            http2.ja4h,
+            "20200n01_846b3a9229ce_000000000000_000000000000"
+        );
+        assert_eq!(HttpResponse::parse(b"SSH-2.0-OpenSSH_9.0\r\n"), None);
+    }
+}
+
+/// Ports the follow-up handshake sends a `GET /` on, unless
+/// [`ScannerBuilder::http`](crate::ScannerBuilder::http) says otherwise.
+pub const HTTP_PORTS: [u16; 4] = [443, 4443, 8443, 9443];
+
+/// A complete handshake with a real TLS stack after the probes, see
+/// [`ScannerBuilder::full_handshake`](crate::ScannerBuilder::full_handshake).
+///
+/// It gets what the probes cannot: the certificates of TLS 1.3 servers, which
+/// are encrypted, and the HTTP response behind the TLS.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handshake {
+    /// Negotiated version as hex, e.g. `0304`
+    pub version: Option<String>,
+    /// Negotiated cipher suite as hex, e.g. `1302`
+    pub cipher: Option<String>,
+    /// Negotiated application layer protocol
+    pub alpn: Option<String>,
+    /// Key exchange group, e.g. `X25519`
+    pub key_exchange: Option<String>,
+    /// The certificate chain the server sent, leaf first
+    pub certificates: Vec<Certificate>,
+    /// The reply to a `GET /` on HTTP ports
+    pub http: Option<HttpResponse>,
+    /// Why the handshake or the request failed
+    pub error: Option<JarmError>,
+}
+
+/// The status line and headers of a server's HTTP response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpResponse {
+    pub status: u16,
+    /// The `Server` header
+    pub server: Option<String>,
+    /// Header names in the order the server sent them
+    pub headers: Vec<String>,
+    /// A JA4H computed over the response instead of a request, e.g. `11301c03_7cd283481512_22090eb1cea3_91c347b4c9c6`
+    ///
+    /// The first part is the HTTP version, the status, `c` or `n` for whether
+    /// there are cookies, and the number of headers besides `Set-Cookie`. Then
+    /// come the truncated SHA-256 of those header names in order, of the
+    /// sorted cookie names, and of the sorted cookies with their values.
+    pub ja4h: String,
+}
+
+impl HttpResponse {
+    /// Parse the head of an HTTP/1.x or HTTP/2 style response, `None` when it
+    /// does not start with a status line.
+    pub fn parse(data: &[u8]) -> Option<HttpResponse> {
+        let end = data
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .unwrap_or(data.len());
+        let head = String::from_utf8_lossy(&data[..end]);
+        let mut lines = head.split("\r\n");
+        let mut status_line = lines.next()?.split(' ');
+        let version = match status_line.next()? {
+            "HTTP/1.0" => "10",
+            "HTTP/1.1" => "11",
+            "HTTP/2" | "HTTP/2.0" => "20",
+            "HTTP/3" => "30",
+            _ => return None,
+        };
+        let status = status_line.next()?.parse().ok()?;
+        let headers: Vec<(&str, &str)> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim(), value.trim()))
+            .collect();
+        let server = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("server"))
+            .map(|(_, value)| value.to_string());
+        Some(HttpResponse {
+            status,
+            server,
+            headers: headers.iter().map(|(name, _)| name.to_string()).collect(),
+            ja4h: ja4h(version, status, &headers),
+        })
+    }
+}
+
+fn ja4h(version: &str, status: u16, headers: &[(&str, &str)]) -> String {
+    let (cookies, others): (Vec<_>, Vec<_>) = headers
+        .iter()
+        .partition(|(name, _)| name.eq_ignore_ascii_case("set-cookie"));
+    let names: Vec<String> = others.iter().map(|(name, _)| name.to_string()).collect();
+    // #Only the name=value of each cookie, not its attributes
+    let mut cookies: Vec<(String, String)> = cookies
+        .iter()
+        .filter_map(|(_, value)| {
+            let cookie = value.split(';').next()?;
+            let (name, value) = cookie.split_once('=').unwrap_or((cookie, ""));
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+    cookies.sort();
+    let cookie_names: Vec<String> = cookies.iter().map(|(name, _)| name.clone()).collect();
+    let cookie_values: Vec<String> = cookies
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    format!(
+        "{}{:03}{}{:02}_{}_{}_{}",
+        version,
+        status,
+        if cookies.is_empty() { 'n' } else { 'c' },
+        names.len().min(99),
+        truncated_hash(&names),
+        truncated_hash(&cookie_names),
+        truncated_hash(&cookie_values)
+    )
+}
+
+// #The follow-up handshake of a scan, when the scanner was built with one
+#[cfg(feature = "handshake")]
+pub(crate) fn follow_up(
+    target: &Target,
+    host: &str,
+    port: u16,
+    config: &Config,
+) -> Option<Handshake> {
+    if !config.full_handshake {
+        return None;
+    }
+    let http = config
+        .http
+        .unwrap_or(config.starttls.is_none() && HTTP_PORTS.contains(&port));
+    Some(crate::tls::handshake(target, host, port, http, config))
+}
+
+#[cfg(not(feature = "handshake"))]
+pub(crate) fn follow_up(
+    _target: &Target,
+    _host: &str,
+    _port: u16,
+    _config: &Config,
+) -> Option<Handshake> {
+    None
+}
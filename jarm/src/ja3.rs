@@ -0,0 +1,61 @@
+use md5::{Digest, Md5};
+
+use crate::ServerHello;
+
+impl ServerHello {
+    /// The JA3S string: version, cipher and extension types in decimal, e.g.
+    /// `771,49199,65281-16-11-23`.
+    pub fn ja3s(&self) -> String {
+        let extensions: Vec<String> = self.extensions.iter().map(|e| e.0.to_string()).collect();
+        format!("{},{},{}", self.version, self.cipher, extensions.join("-"))
+    }
+    /// The MD5 of [`ja3s`](ServerHello::ja3s), as JA3S is usually shared.
+    pub fn ja3s_hash(&self) -> String {
+        ja3s_hash(&self.ja3s())
+    }
+}
+
+pub(crate) fn ja3s_hash(ja3s: &str) -> String {
+    hex::encode(Md5::digest(ja3s.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ProbeResult, ServerHello};
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Corpus {
+        hello: Vec<Known>,
+    }
+
+    #[derive(Deserialize)]
+    struct Known {
+        name: String,
+        data: String,
+        ja3s: String,
+        ja3s_hash: String,
+    }
+
+    // Known server hellos, see tests/golden/server_hellos.py
+    fn corpus() -> Vec<Known> {
+        let corpus: Corpus =
+            toml::from_str(include_str!("../tests/golden/server_hellos.toml")).unwrap();
+        corpus.hello
+    }
+
+    #[test]
+    fn known_server_hellos() {
+        for known in corpus() {
+            let data = hex::decode(&known.data).unwrap();
+            let hello = ServerHello::parse(&data).unwrap();
+            assert_eq!(hello.ja3s(), known.ja3s, "{}", known.name);
+            assert_eq!(hello.ja3s_hash(), known.ja3s_hash, "{}", known.name);
+            let probe = ProbeResult::from_response("tls1_2_forward", &data);
+            assert_eq!(probe.ja3s.as_deref(), Some(known.ja3s.as_str()));
+            assert_eq!(probe.ja3s_hash.as_deref(), Some(known.ja3s_hash.as_str()));
+        }
+        let alert = ProbeResult::from_response("tls1_2_forward", b"\x15\x03\x03\x00\x02\x02\x28");
+        assert_eq!(alert.ja3s, None);
+    }
+}
@@ -0,0 +1,191 @@
+use sha2::{Digest, Sha256};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::prelude::FromDer;
+use x509_parser::x509::X509Name;
+
+use crate::ServerHello;
+
+#[cfg(test)]
+mod tests {
+    use crate::{ja4x, ja4x_raw, ProbeResult, ServerHello};
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Corpus {
+        hello: Vec<Known>,
+    }
+
+    #[derive(Deserialize)]
+    struct Known {
+        name: String,
+        data: String,
+        ja4s: String,
+        ja4s_raw: String,
+        ja4x: Option<String>,
+        ja4x_raw: Option<String>,
+    }
+
+    #[test]
+    fn known_server_hellos() {
+        let corpus: Corpus =
+            toml::from_str(include_str!("../tests/golden/server_hellos.toml")).unwrap();
+        for known in corpus.hello {
+            let data = hex::decode(&known.data).unwrap();
+            let hello = ServerHello::parse(&data).unwrap();
+            assert_eq!(hello.ja4s(), known.ja4s, "{}", known.name);
+            assert_eq!(hello.ja4s_raw(), known.ja4s_raw, "{}", known.name);
+            let probe = ProbeResult::from_response("tls1_2_forward", &data);
+            assert_eq!(probe.ja4s.as_deref(), Some(known.ja4s.as_str()));
+            assert_eq!(probe.ja4x, known.ja4x, "{}", known.name);
+            let leaf = crate::parser::certificate_chain(&data).map(|chain| chain[0].clone());
+            assert_eq!(leaf.as_deref().and_then(ja4x_raw), known.ja4x_raw);
+        }
+    }
+
+    #[test]
+    fn not_a_certificate() {
+        assert_eq!(ja4x(b""), None);
+        assert_eq!(ja4x(b"\x30\x03\x02\x01\x01"), None);
+    }
+}
+
+const SUPPORTED_VERSIONS_EXTENSION: u16 = 0x002b;
+const ALPN_EXTENSION: u16 = 0x0010;
+
+impl ServerHello {
+    /// The JA4S fingerprint: protocol, version, extension count and ALPN, the
+    /// cipher, and a hash of the extension types in the order the server sent
+    /// them, e.g. `t1204h2_c02f_594cf10ab965`.
+    pub fn ja4s(&self) -> String {
+        format!(
+            "{}_{:04x}_{}",
+            self.ja4s_a(),
+            self.cipher,
+            truncated_hash(&self.ja4s_extensions())
+        )
+    }
+    /// [`ja4s`](ServerHello::ja4s) with the extension types listed instead of hashed.
+    pub fn ja4s_raw(&self) -> String {
+        format!(
+            "{}_{:04x}_{}",
+            self.ja4s_a(),
+            self.cipher,
+            self.ja4s_extensions().join(",")
+        )
+    }
+    fn ja4s_a(&self) -> String {
+        // #TLS 1.3 keeps 0x0303 in the legacy field and selects the version in an extension
+        let version = self
+            .extensions
+            .iter()
+            .find(|e| e.0 == SUPPORTED_VERSIONS_EXTENSION)
+            .and_then(|(_, value)| Some(u16::from_be_bytes([*value.first()?, *value.get(1)?])))
+            .unwrap_or(self.version);
+        let alpn = self
+            .extensions
+            .iter()
+            .find(|e| e.0 == ALPN_EXTENSION)
+            .and_then(|(_, value)| {
+                let length = *value.get(2)? as usize;
+                value.get(3..3 + length)
+            })
+            .unwrap_or_default();
+        format!(
+            "t{}{:02}{}",
+            version_code(version),
+            self.extensions.len().min(99),
+            alpn_code(alpn)
+        )
+    }
+    fn ja4s_extensions(&self) -> Vec<String> {
+        self.extensions
+            .iter()
+            .map(|(t, _)| format!("{:04x}", t))
+            .collect()
+    }
+}
+
+fn version_code(version: u16) -> &'static str {
+    match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        0x0002 => "s2",
+        0xfeff => "d1",
+        0xfefd => "d2",
+        0xfefc => "d3",
+        _ => "00",
+    }
+}
+
+// #First and last character of the protocol, of its hex when they are not alphanumeric
+fn alpn_code(protocol: &[u8]) -> String {
+    match (protocol.first(), protocol.last()) {
+        (Some(first), Some(last))
+            if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() =>
+        {
+            format!("{}{}", *first as char, *last as char)
+        }
+        (Some(_), Some(_)) => {
+            let hex = hex::encode(protocol);
+            format!("{}{}", &hex[..1], &hex[hex.len() - 1..])
+        }
+        _ => "00".to_string(),
+    }
+}
+
+// #The first 12 hex characters of the SHA-256 of the comma separated items
+pub(crate) fn truncated_hash(items: &[String]) -> String {
+    if items.is_empty() {
+        return "000000000000".to_string();
+    }
+    let hash = hex::encode(Sha256::digest(items.join(",").as_bytes()));
+    hash[..12].to_string()
+}
+
+/// The JA4X fingerprint of a DER encoded X.509 certificate: hashes of the
+/// issuer attribute, subject attribute and extension OIDs, in certificate
+/// order, e.g. `a373a9f83c6b_a373a9f83c6b_3b53d8d4606c`.
+///
+/// `None` when the bytes are not a certificate.
+pub fn ja4x(der: &[u8]) -> Option<String> {
+    let [issuer, subject, extensions] = ja4x_oids(der)?;
+    Some(format!(
+        "{}_{}_{}",
+        truncated_hash(&issuer),
+        truncated_hash(&subject),
+        truncated_hash(&extensions)
+    ))
+}
+
+/// [`ja4x`] with the OIDs listed in hex instead of hashed.
+pub fn ja4x_raw(der: &[u8]) -> Option<String> {
+    let [issuer, subject, extensions] = ja4x_oids(der)?;
+    Some(format!(
+        "{}_{}_{}",
+        issuer.join(","),
+        subject.join(","),
+        extensions.join(",")
+    ))
+}
+
+fn ja4x_oids(der: &[u8]) -> Option<[Vec<String>; 3]> {
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    let name_oids = |name: &X509Name| -> Vec<String> {
+        name.iter_attributes()
+            .map(|a| hex::encode(a.attr_type().as_bytes()))
+            .collect()
+    };
+    let extensions = cert
+        .extensions()
+        .iter()
+        .map(|e| hex::encode(e.oid.as_bytes()))
+        .collect();
+    Some([
+        name_oids(cert.issuer()),
+        name_oids(cert.subject()),
+        extensions,
+    ])
+}
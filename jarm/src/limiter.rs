@@ -0,0 +1,73 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use crate::RateLimiter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn spaces_out_attempts() {
+        let limiter = RateLimiter::per_second(20);
+        let shared = limiter.clone();
+        let start = Instant::now();
+        let first = limiter.reserve();
+        let second = shared.reserve();
+        let third = limiter.reserve();
+        assert!(first <= start + Duration::from_millis(5));
+        assert_eq!(second - first, Duration::from_millis(50));
+        assert_eq!(third - second, Duration::from_millis(50));
+        limiter.wait();
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+}
+
+/// Spaces out connection attempts, shared by every scanner it is cloned into.
+///
+/// ```no_run
+/// use jarm::{RateLimiter, Scanner};
+///
+/// let limiter = RateLimiter::per_second(50);
+/// for host in ["a.example", "b.example"] {
+///     let scanner = Scanner::builder(host, 443)
+///         .rate_limiter(limiter.clone())
+///         .build()
+///         .unwrap();
+///     println!("{}", scanner.fingerprint());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RateLimiter {
+    /// At most `rate` connection attempts per second, at least one.
+    pub fn per_second(rate: u32) -> Self {
+        RateLimiter::new(Duration::from_secs(1) / rate.max(1))
+    }
+    /// One connection attempt every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        RateLimiter {
+            interval,
+            next: Arc::new(Mutex::new(None)),
+        }
+    }
+    /// Take the next free slot and return when it starts.
+    pub(crate) fn reserve(&self) -> Instant {
+        let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let slot = next.map_or(now, |next| next.max(now));
+        *next = Some(slot + self.interval);
+        slot
+    }
+    /// Block until the next free slot.
+    pub(crate) fn wait(&self) {
+        let slot = self.reserve();
+        let now = Instant::now();
+        if slot > now {
+            std::thread::sleep(slot - now);
+        }
+    }
+}
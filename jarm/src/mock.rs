@@ -0,0 +1,216 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::capture::{hello_shape, probe_shapes};
+use crate::{JarmFingerprint, ProbeSet};
+
+#[cfg(test)]
+mod tests {
+    use crate::{Fixture, JarmError, MockServer, Scanner};
+    use std::collections::BTreeMap;
+
+    const OPENSSL: &str = include_str!("../tests/fixtures/openssl.toml");
+    const LEGACY: &str = include_str!("../tests/fixtures/legacy.toml");
+
+    #[test]
+    fn replays_fixtures() {
+        for text in [OPENSSL, LEGACY] {
+            let fixture = Fixture::from_toml(text).unwrap();
+            let server = fixture.serve().unwrap();
+            let result = Scanner::new("127.0.0.1".to_string(), server.port())
+                .unwrap()
+                .scan();
+            assert_eq!(result.fingerprint, fixture.fingerprint, "{}", fixture.name);
+            assert!(result.probes.iter().all(|p| p.bytes_read > 0));
+        }
+    }
+
+    #[test]
+    fn missing_reply_closes() {
+        let mut fixture = Fixture::from_toml(OPENSSL).unwrap();
+        fixture.replies.remove("tls1_3_middle_out");
+        let server = fixture.serve().unwrap();
+        let result = Scanner::builder("127.0.0.1", server.port())
+            .retries(0)
+            .build()
+            .unwrap()
+            .scan();
+        assert_eq!(result.probes[9].error, Some(JarmError::ConnectionClosed));
+        assert_ne!(result.fingerprint, fixture.fingerprint);
+        // nothing to replay at all
+        let server = MockServer::start(BTreeMap::new()).unwrap();
+        let scanner = Scanner::builder("127.0.0.1", server.port())
+            .retries(0)
+            .build()
+            .unwrap();
+        assert!(scanner.fingerprint().is_zero());
+        assert!(Fixture::from_toml("name = \"x\"\nfingerprint = \"00\"\n").is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_replay() {
+        let fixture = Fixture::from_toml(OPENSSL).unwrap();
+        let server = fixture.serve().unwrap();
+        let scanner = crate::Scanner::builder("127.0.0.1", server.port())
+            .build_async()
+            .await
+            .unwrap();
+        assert_eq!(scanner.fingerprint().await, fixture.fingerprint);
+    }
+}
+
+/// Canned replies to the ten probes and the fingerprint they make.
+///
+/// Fixtures are TOML files with a `name`, the expected `fingerprint` and a
+/// `[replies]` table of hex encoded server responses keyed by probe name, see
+/// `jarm/tests/fixtures`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    pub name: String,
+    pub fingerprint: JarmFingerprint,
+    /// The raw reply to each probe, probes without one get the connection closed
+    pub replies: BTreeMap<String, Vec<u8>>,
+}
+
+#[derive(Deserialize)]
+struct FixtureFile {
+    name: String,
+    fingerprint: JarmFingerprint,
+    #[serde(default)]
+    replies: BTreeMap<String, String>,
+}
+
+fn invalid(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl Fixture {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Fixture::from_toml(&std::fs::read_to_string(path)?)
+    }
+    pub fn from_toml(text: &str) -> io::Result<Self> {
+        let file: FixtureFile = toml::from_str(text).map_err(invalid)?;
+        let mut replies = BTreeMap::new();
+        let set = ProbeSet::jarm();
+        for (probe, reply) in file.replies {
+            if !set.probes().iter().any(|p| p.name == probe) {
+                return Err(invalid(format!("unknown probe {}", probe)));
+            }
+            let reply =
+                hex::decode(reply.trim()).map_err(|e| invalid(format!("{}: {}", probe, e)))?;
+            replies.insert(probe, reply);
+        }
+        Ok(Fixture {
+            name: file.name,
+            fingerprint: file.fingerprint,
+            replies,
+        })
+    }
+    /// Start a [`MockServer`] replaying the replies.
+    pub fn serve(&self) -> io::Result<MockServer> {
+        MockServer::start(self.replies.clone())
+    }
+}
+
+/// A TCP server on localhost that answers each JARM probe with a canned reply.
+///
+/// It tells the probes apart by the shape of their ClientHello, writes the
+/// reply for that probe name and closes the connection. The server stops
+/// when it is dropped.
+///
+/// ```
+/// use jarm::{Fixture, Scanner};
+///
+/// let fixture = Fixture::load("tests/fixtures/openssl.toml").unwrap();
+/// let server = fixture.serve().unwrap();
+/// let scanner = Scanner::new("127.0.0.1".to_string(), server.port()).unwrap();
+/// assert_eq!(scanner.fingerprint(), fixture.fingerprint);
+/// ```
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    /// Listen on a free port of 127.0.0.1, with the replies keyed by probe name.
+    pub fn start(replies: BTreeMap<String, Vec<u8>>) -> io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        // #Replies in probe order, found by the position of the probe's shape
+        let replies: Vec<Option<Vec<u8>>> = ProbeSet::jarm()
+            .probes()
+            .iter()
+            .map(|p| replies.get(&p.name).cloned())
+            .collect();
+        let replies = Arc::new(replies);
+        let shapes = Arc::new(probe_shapes());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let (replies, shapes) = (replies.clone(), shapes.clone());
+                // #One thread per connection, the async scanner sends every probe at once
+                if let Ok(stream) = stream {
+                    std::thread::spawn(move || {
+                        let _ = answer(stream, &replies, &shapes);
+                    });
+                }
+            }
+        });
+        Ok(MockServer { addr, stop })
+    }
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // wake up the accept loop so it sees the flag
+        let _ = TcpStream::connect_timeout(&self.addr, Duration::from_secs(1));
+    }
+}
+
+// #Read one ClientHello record, write the reply for its probe and close
+fn answer(
+    mut stream: TcpStream,
+    replies: &[Option<Vec<u8>>],
+    shapes: &[Vec<u8>],
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut hello = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        if let Some(length) = hello.get(3..5) {
+            if hello.len() >= 5 + u16::from_be_bytes([length[0], length[1]]) as usize {
+                break;
+            }
+        }
+        match stream.read(&mut buf)? {
+            0 => return Ok(()),
+            n => hello.extend_from_slice(&buf[..n]),
+        }
+    }
+    let reply = hello_shape(&hello)
+        .and_then(|(shape, _)| shapes.iter().position(|s| *s == shape))
+        .and_then(|index| replies[index].as_ref());
+    if let Some(reply) = reply {
+        stream.write_all(reply)?;
+    }
+    stream.shutdown(Shutdown::Both)
+}
@@ -0,0 +1,213 @@
+use crate::JarmError;
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::server_hello;
+    use crate::{JarmError, ServerHello};
+
+    #[test]
+    fn parse_server_hello() {
+        let data = server_hello();
+        let hello = ServerHello::parse(&data).unwrap();
+        assert_eq!(hello.version, 0x0303);
+        assert_eq!(hello.session_id.len(), 32);
+        assert_eq!(hello.cipher, 0xc02f);
+        assert_eq!(hello.compression, 0);
+        let types: Vec<u16> = hello.extensions.iter().map(|e| e.0).collect();
+        assert_eq!(types, [0xff01, 0x0010, 0x000b, 0x0017]);
+        assert_eq!(hello.alpn().as_deref(), Some("h2"));
+    }
+
+    #[test]
+    fn truncated_never_panics() {
+        let data = server_hello();
+        for len in 0..data.len() {
+            assert!(ServerHello::parse(&data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn extension_length_overflow() {
+        let mut data = server_hello();
+        // claim the last extension is longer than the message
+        let last = data.len() - 1;
+        data[last] = 0xff;
+        assert_eq!(
+            ServerHello::parse(&data),
+            Err(JarmError::MalformedServerHello)
+        );
+    }
+
+    #[test]
+    fn no_extensions() {
+        let hello = "160303004a0200004603030000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000000c02f00";
+        let hello = ServerHello::parse(&hex::decode(hello).unwrap()).unwrap();
+        assert!(hello.extensions.is_empty());
+        assert_eq!(hello.alpn(), None);
+    }
+}
+
+const HANDSHAKE: u8 = 22;
+const ALERT: u8 = 21;
+const SERVER_HELLO: u8 = 2;
+const CERTIFICATE: u8 = 11;
+const ALPN_EXTENSION: u16 = 0x0010;
+
+// Bounds checked reader over a server response
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+    pub(crate) fn bytes(&mut self, n: usize) -> Result<&'a [u8], JarmError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .ok_or(JarmError::MalformedServerHello)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(JarmError::MalformedServerHello)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+    pub(crate) fn u8(&mut self) -> Result<u8, JarmError> {
+        Ok(self.bytes(1)?[0])
+    }
+    pub(crate) fn u16(&mut self) -> Result<u16, JarmError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+    pub(crate) fn u24(&mut self) -> Result<usize, JarmError> {
+        let b = self.bytes(3)?;
+        Ok(((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize)
+    }
+    // A sub reader over the next `n` bytes
+    pub(crate) fn sub(&mut self, n: usize) -> Result<Reader<'a>, JarmError> {
+        Ok(Reader::new(self.bytes(n)?))
+    }
+}
+
+/// A parsed ServerHello handshake message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerHello {
+    /// The legacy version field, e.g. `0x0303`
+    pub version: u16,
+    pub random: Vec<u8>,
+    pub session_id: Vec<u8>,
+    pub cipher: u16,
+    pub compression: u8,
+    /// Extension types and their data, in the order the server sent them
+    pub extensions: Vec<(u16, Vec<u8>)>,
+}
+
+impl ServerHello {
+    /// Parse the server hello at the start of a server's response.
+    ///
+    /// Never panics: truncated or malformed input returns an error, as do
+    /// alerts and responses that are not TLS at all.
+    pub fn parse(data: &[u8]) -> Result<ServerHello, JarmError> {
+        let mut reader = Reader::new(data);
+        let content_type = reader.u8().map_err(|_| JarmError::NonTls)?;
+        match content_type {
+            HANDSHAKE => {}
+            ALERT => {
+                let mut alert = Reader::new(data.get(5..).unwrap_or_default());
+                return Err(JarmError::Alert {
+                    level: alert.u8()?,
+                    description: alert.u8()?,
+                });
+            }
+            _ => return Err(JarmError::NonTls),
+        }
+        let _record_version = reader.u16()?;
+        let record_length = reader.u16()? as usize;
+        // The record may hold more than the server hello, only the hello has to be complete
+        let record = &data[5..];
+        let record = record.get(..record_length).unwrap_or(record);
+        ServerHello::parse_message(record)
+    }
+    // #Parse a handshake message that should be a server hello
+    pub(crate) fn parse_message(data: &[u8]) -> Result<ServerHello, JarmError> {
+        let mut reader = Reader::new(data);
+        if reader.u8()? != SERVER_HELLO {
+            return Err(JarmError::MalformedServerHello);
+        }
+        let length = reader.u24()?;
+        let mut body = reader.sub(length)?;
+
+        let version = body.u16()?;
+        let random = body.bytes(32)?.to_vec();
+        let session_id_length = body.u8()? as usize;
+        let session_id = body.bytes(session_id_length)?.to_vec();
+        let cipher = body.u16()?;
+        let compression = body.u8()?;
+
+        let mut extensions = Vec::new();
+        if !body.is_empty() {
+            let extensions_length = body.u16()? as usize;
+            let mut block = body.sub(extensions_length)?;
+            while !block.is_empty() {
+                let ext_type = block.u16()?;
+                let ext_length = block.u16()? as usize;
+                extensions.push((ext_type, block.bytes(ext_length)?.to_vec()));
+            }
+        }
+        Ok(ServerHello {
+            version,
+            random,
+            session_id,
+            cipher,
+            compression,
+            extensions,
+        })
+    }
+    /// The application layer protocol the server selected.
+    pub fn alpn(&self) -> Option<String> {
+        let (_, value) = self.extensions.iter().find(|e| e.0 == ALPN_EXTENSION)?;
+        // skip the list length and the protocol length
+        let protocol = std::str::from_utf8(value.get(3..)?).ok()?;
+        if protocol.is_empty() {
+            return None;
+        }
+        Some(protocol.to_string())
+    }
+}
+
+/// The DER certificates of the plaintext Certificate message following the
+/// server hello of a TLS 1.2 response, leaf first.
+///
+/// `None` when the response ends before the message is complete, or the
+/// server negotiated TLS 1.3 and encrypted it.
+pub(crate) fn certificate_chain(data: &[u8]) -> Option<Vec<Vec<u8>>> {
+    if data.first() != Some(&HANDSHAKE) {
+        return None;
+    }
+    let record_length = u16::from_be_bytes([*data.get(3)?, *data.get(4)?]) as usize;
+    let record = &data[5..];
+    let mut messages = Reader::new(record.get(..record_length).unwrap_or(record));
+    while !messages.is_empty() {
+        let kind = messages.u8().ok()?;
+        let length = messages.u24().ok()?;
+        let mut body = messages.sub(length).ok()?;
+        if kind != CERTIFICATE {
+            continue;
+        }
+        let list_length = body.u24().ok()?;
+        let mut list = body.sub(list_length).ok()?;
+        let mut chain = Vec::new();
+        while !list.is_empty() {
+            let length = list.u24().ok()?;
+            chain.push(list.bytes(length).ok()?.to_vec());
+        }
+        return Some(chain);
+    }
+    None
+}
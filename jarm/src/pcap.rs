@@ -0,0 +1,512 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::CaptureError;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::pcap::{segments, Segment, ACK, FIN, SYN};
+    use crate::CaptureError;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    // Ethernet, IPv4 or IPv6 and TCP headers around `payload`
+    pub(crate) fn frame(
+        src: SocketAddr,
+        dst: SocketAddr,
+        seq: u32,
+        flags: u8,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut tcp = Vec::new();
+        tcp.extend(src.port().to_be_bytes());
+        tcp.extend(dst.port().to_be_bytes());
+        tcp.extend(seq.to_be_bytes());
+        tcp.extend(0u32.to_be_bytes());
+        tcp.extend([5 << 4, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        tcp.extend(payload);
+        let mut frame = vec![0; 12];
+        match (src, dst) {
+            (SocketAddr::V4(src), SocketAddr::V4(dst)) => {
+                frame.extend([0x08, 0x00, 0x45, 0]);
+                frame.extend((20 + tcp.len() as u16).to_be_bytes());
+                frame.extend([0, 0, 0x40, 0, 64, 6, 0, 0]);
+                frame.extend(src.ip().octets());
+                frame.extend(dst.ip().octets());
+            }
+            (SocketAddr::V6(src), SocketAddr::V6(dst)) => {
+                frame.extend([0x86, 0xdd, 0x60, 0, 0, 0]);
+                frame.extend((tcp.len() as u16).to_be_bytes());
+                frame.extend([6, 64]);
+                frame.extend(src.ip().octets());
+                frame.extend(dst.ip().octets());
+            }
+            _ => unreachable!("mixed address families"),
+        }
+        frame.extend(tcp);
+        frame
+    }
+
+    // A little endian pcap of ethernet frames, a packet every millisecond
+    pub(crate) fn pcap(frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        data.extend([0; 8]);
+        data.extend(65535u32.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        for (i, frame) in frames.iter().enumerate() {
+            data.extend(1_600_000_000u32.to_le_bytes());
+            data.extend((i as u32 * 1000).to_le_bytes());
+            data.extend((frame.len() as u32).to_le_bytes());
+            data.extend((frame.len() as u32).to_le_bytes());
+            data.extend(frame);
+        }
+        data
+    }
+
+    fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+        let padded = body.len().div_ceil(4) * 4;
+        let length = (12 + padded) as u32;
+        let mut block = Vec::new();
+        block.extend(kind.to_be_bytes());
+        block.extend(length.to_be_bytes());
+        block.extend(body);
+        block.resize(8 + padded, 0);
+        block.extend(length.to_be_bytes());
+        block
+    }
+
+    // A big endian pcapng with an if_tsresol of 10^-`tsresol`, every packet at `ticks`
+    pub(crate) fn pcapng(frames: &[Vec<u8>], tsresol: u8, ticks: u64) -> Vec<u8> {
+        let mut data = block(
+            0x0a0d0d0a,
+            &[
+                0x1a, 0x2b, 0x3c, 0x4d, 0, 1, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            ],
+        );
+        data.extend(block(
+            1,
+            &[
+                0, 1, 0, 0, 0, 0, 0, 0, 0, 9, 0, 1, tsresol, 0, 0, 0, 0, 0, 0, 0,
+            ],
+        ));
+        for frame in frames {
+            let mut body = Vec::new();
+            body.extend(0u32.to_be_bytes());
+            body.extend(((ticks >> 32) as u32).to_be_bytes());
+            body.extend((ticks as u32).to_be_bytes());
+            body.extend((frame.len() as u32).to_be_bytes());
+            body.extend((frame.len() as u32).to_be_bytes());
+            body.extend(frame);
+            data.extend(block(6, &body));
+        }
+        data
+    }
+
+    #[test]
+    fn pcap_and_pcapng() {
+        let client: SocketAddr = "10.0.0.1:50000".parse().unwrap();
+        let server: SocketAddr = "[2001:db8::1]:443".parse().unwrap();
+        let v6_client: SocketAddr = "[2001:db8::2]:50000".parse().unwrap();
+        let frames = [
+            frame(client, "10.0.0.2:443".parse().unwrap(), 7, SYN, b""),
+            frame(server, v6_client, 9, ACK | FIN, b"hello"),
+        ];
+        let expected = [
+            Segment {
+                timestamp: Duration::from_secs(1_600_000_000),
+                src: client,
+                dst: "10.0.0.2:443".parse().unwrap(),
+                seq: 7,
+                flags: SYN,
+                payload: Vec::new(),
+            },
+            Segment {
+                timestamp: Duration::from_millis(1_600_000_000_001),
+                src: server,
+                dst: v6_client,
+                seq: 9,
+                flags: ACK | FIN,
+                payload: b"hello".to_vec(),
+            },
+        ];
+        assert_eq!(segments(&pcap(&frames)).unwrap(), expected);
+        let from_pcapng = segments(&pcapng(&frames, 9, 1_600_000_000_000_000_000)).unwrap();
+        assert_eq!(from_pcapng[1].payload, b"hello");
+        assert_eq!(from_pcapng[0].timestamp, Duration::from_secs(1_600_000_000));
+        // a capture cut off mid packet keeps what came before
+        let data = pcap(&frames);
+        assert_eq!(segments(&data[..data.len() - 3]).unwrap().len(), 1);
+        assert!(matches!(
+            segments(b"not a capture"),
+            Err(CaptureError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn segmentation_offload() {
+        let v4 = frame(
+            "10.0.0.2:443".parse().unwrap(),
+            "10.0.0.1:50000".parse().unwrap(),
+            1,
+            ACK,
+            b"hello",
+        );
+        let v6 = frame(
+            "[2001:db8::1]:443".parse().unwrap(),
+            "[2001:db8::2]:50000".parse().unwrap(),
+            1,
+            ACK,
+            b"hello",
+        );
+        // a locally captured segment before the NIC split it has no IP length
+        let mut frames = [v4, v6];
+        frames[0][16..18].fill(0);
+        frames[1][18..20].fill(0);
+        let segments = segments(&pcap(&frames)).unwrap();
+        assert_eq!(segments[0].payload, b"hello");
+        assert_eq!(segments[1].payload, b"hello");
+    }
+}
+
+pub(crate) const FIN: u8 = 0x01;
+pub(crate) const SYN: u8 = 0x02;
+pub(crate) const RST: u8 = 0x04;
+pub(crate) const ACK: u8 = 0x10;
+
+// Link layer header types, from https://www.tcpdump.org/linktypes.html
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// One TCP segment from a capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Segment {
+    /// Since the Unix epoch
+    pub(crate) timestamp: Duration,
+    pub(crate) src: SocketAddr,
+    pub(crate) dst: SocketAddr,
+    pub(crate) seq: u32,
+    pub(crate) flags: u8,
+    pub(crate) payload: Vec<u8>,
+}
+
+// A captured frame and the link layer it starts with
+struct Packet<'a> {
+    timestamp: Duration,
+    link_type: u32,
+    data: &'a [u8],
+}
+
+// Bounds checked reads in the byte order of the capture file
+#[derive(Clone, Copy)]
+struct Bytes<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Bytes<'a> {
+    fn get(&self, at: usize, len: usize) -> Option<&'a [u8]> {
+        self.data.get(at..at.checked_add(len)?)
+    }
+    fn u16(&self, at: usize) -> Option<u16> {
+        let b: [u8; 2] = self.get(at, 2)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u16::from_be_bytes(b),
+            false => u16::from_le_bytes(b),
+        })
+    }
+    fn u32(&self, at: usize) -> Option<u32> {
+        let b: [u8; 4] = self.get(at, 4)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u32::from_be_bytes(b),
+            false => u32::from_le_bytes(b),
+        })
+    }
+}
+
+/// Every TCP segment in a pcap or pcapng file, in capture order.
+///
+/// Frames with another link layer, network or transport protocol are
+/// skipped, and so is a last packet cut off by the end of the file.
+pub(crate) fn segments(data: &[u8]) -> Result<Vec<Segment>, CaptureError> {
+    let packets = match data.get(..4) {
+        Some([0x0a, 0x0d, 0x0d, 0x0a]) => pcapng_packets(data)?,
+        Some(_) => pcap_packets(data)?,
+        None => return Err(CaptureError::Format("file is too short".to_string())),
+    };
+    Ok(packets
+        .iter()
+        .filter_map(|p| {
+            let (ip, payload) = link_payload(p.link_type, p.data)?;
+            tcp_segment(p.timestamp, ip, payload)
+        })
+        .collect())
+}
+
+fn pcap_packets(data: &[u8]) -> Result<Vec<Packet<'_>>, CaptureError> {
+    // #The magic number tells the byte order and whether timestamps are in micro or nanoseconds
+    let (big_endian, nanos) = match data.get(..4) {
+        Some([0xd4, 0xc3, 0xb2, 0xa1]) => (false, false),
+        Some([0x4d, 0x3c, 0xb2, 0xa1]) => (false, true),
+        Some([0xa1, 0xb2, 0xc3, 0xd4]) => (true, false),
+        Some([0xa1, 0xb2, 0x3c, 0x4d]) => (true, true),
+        _ => {
+            return Err(CaptureError::Format(
+                "not a pcap or pcapng file".to_string(),
+            ))
+        }
+    };
+    let bytes = Bytes { data, big_endian };
+    let link_type = bytes
+        .u32(20)
+        .ok_or_else(|| CaptureError::Format("truncated pcap header".to_string()))?
+        & 0xffff;
+    let mut packets = Vec::new();
+    let mut at = 24;
+    while let (Some(secs), Some(fraction), Some(len)) =
+        (bytes.u32(at), bytes.u32(at + 4), bytes.u32(at + 8))
+    {
+        let frame = match bytes.get(at + 16, len as usize) {
+            Some(frame) => frame,
+            None => break,
+        };
+        let fraction = match nanos {
+            true => Duration::from_nanos(fraction as u64),
+            false => Duration::from_micros(fraction as u64),
+        };
+        packets.push(Packet {
+            timestamp: Duration::from_secs(secs as u64) + fraction,
+            link_type,
+            data: frame,
+        });
+        at += 16 + len as usize;
+    }
+    Ok(packets)
+}
+
+// #An interface of a pcapng section, timestamps are in units of 1/resolution seconds
+struct Interface {
+    link_type: u32,
+    resolution: u64,
+}
+
+fn pcapng_packets(data: &[u8]) -> Result<Vec<Packet<'_>>, CaptureError> {
+    let mut packets = Vec::new();
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut bytes = Bytes {
+        data,
+        big_endian: false,
+    };
+    let mut at = 0;
+    while let Some(kind) = bytes.u32(at) {
+        if kind == 0x0a0d0d0a {
+            // #A section header block resets the byte order and the interfaces
+            bytes.big_endian = match bytes.get(at + 8, 4) {
+                Some([0x1a, 0x2b, 0x3c, 0x4d]) => true,
+                Some([0x4d, 0x3c, 0x2b, 0x1a]) => false,
+                _ => {
+                    return Err(CaptureError::Format(
+                        "bad pcapng byte order magic".to_string(),
+                    ))
+                }
+            };
+            interfaces.clear();
+        }
+        let len = match bytes.u32(at + 4) {
+            Some(len) if len >= 12 && len % 4 == 0 => len as usize,
+            _ => break,
+        };
+        let body = match bytes.get(at + 8, len - 12) {
+            Some(body) => Bytes {
+                data: body,
+                ..bytes
+            },
+            None => break,
+        };
+        match kind {
+            // interface description
+            1 => interfaces.push(Interface {
+                link_type: body.u16(0).unwrap_or(u16::MAX) as u32,
+                resolution: timestamp_resolution(body),
+            }),
+            // enhanced packet
+            6 => {
+                let (id, high, low, captured) =
+                    match (body.u32(0), body.u32(4), body.u32(8), body.u32(12)) {
+                        (Some(id), Some(high), Some(low), Some(captured)) => {
+                            (id, high, low, captured)
+                        }
+                        _ => break,
+                    };
+                let (interface, frame) =
+                    match (interfaces.get(id as usize), body.get(20, captured as usize)) {
+                        (Some(interface), Some(frame)) => (interface, frame),
+                        _ => break,
+                    };
+                let ticks = ((high as u64) << 32) | low as u64;
+                packets.push(Packet {
+                    timestamp: ticks_to_duration(ticks, interface.resolution),
+                    link_type: interface.link_type,
+                    data: frame,
+                });
+            }
+            // simple packet, always from the first interface and without a timestamp
+            3 => {
+                if let (Some(interface), Some(original)) = (interfaces.first(), body.u32(0)) {
+                    let captured = (original as usize).min(body.data.len().saturating_sub(4));
+                    packets.push(Packet {
+                        timestamp: Duration::ZERO,
+                        link_type: interface.link_type,
+                        data: &body.data[4..4 + captured],
+                    });
+                }
+            }
+            _ => {}
+        }
+        at += len;
+    }
+    if interfaces.is_empty() && packets.is_empty() && at == 0 {
+        return Err(CaptureError::Format(
+            "truncated pcapng section header".to_string(),
+        ));
+    }
+    Ok(packets)
+}
+
+// #The if_tsresol option, 10^-6 seconds when it is missing
+fn timestamp_resolution(body: Bytes) -> u64 {
+    let mut at = 8;
+    while let (Some(code), Some(len)) = (body.u16(at), body.u16(at + 2)) {
+        if code == 0 {
+            break;
+        }
+        if code == 9 && len == 1 {
+            let value = body.get(at + 4, 1).map_or(6, |v| v[0]);
+            let exponent = (value & 0x7f) as u32;
+            return match value & 0x80 {
+                0 => 10u64.checked_pow(exponent),
+                _ => 2u64.checked_pow(exponent),
+            }
+            .unwrap_or(1_000_000);
+        }
+        at += 4 + (len as usize).div_ceil(4) * 4;
+    }
+    1_000_000
+}
+
+fn ticks_to_duration(ticks: u64, resolution: u64) -> Duration {
+    let secs = ticks / resolution;
+    let rest = (ticks % resolution) as u128 * 1_000_000_000 / resolution as u128;
+    Duration::new(secs, rest as u32)
+}
+
+// #The network layer packet inside a frame, with its IP version
+fn link_payload(link_type: u32, data: &[u8]) -> Option<(u8, &[u8])> {
+    let ip_version = |data: &[u8]| data.first().map(|b| b >> 4);
+    let (ethertype, payload) = match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut at = 12;
+            let mut ethertype = u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?);
+            // skip 802.1Q and 802.1ad tags
+            while matches!(ethertype, 0x8100 | 0x88a8) {
+                at += 4;
+                ethertype = u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?);
+            }
+            (ethertype, data.get(at + 2..)?)
+        }
+        LINKTYPE_NULL | LINKTYPE_LOOP => {
+            // the address family, in host byte order for NULL
+            let family = data.get(..4)?;
+            let family = if family[0] != 0 { family[0] } else { family[3] };
+            let ethertype = match family {
+                2 => 0x0800,
+                24 | 28 | 30 => 0x86dd,
+                _ => return None,
+            };
+            (ethertype, data.get(4..)?)
+        }
+        LINKTYPE_LINUX_SLL => (
+            u16::from_be_bytes(data.get(14..16)?.try_into().ok()?),
+            data.get(16..)?,
+        ),
+        LINKTYPE_LINUX_SLL2 => (
+            u16::from_be_bytes(data.get(..2)?.try_into().ok()?),
+            data.get(20..)?,
+        ),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 | 12 | 14 => {
+            return Some((ip_version(data)?, data));
+        }
+        _ => return None,
+    };
+    match ethertype {
+        0x0800 => Some((4, payload)),
+        0x86dd => Some((6, payload)),
+        _ => None,
+    }
+}
+
+fn tcp_segment(timestamp: Duration, version: u8, data: &[u8]) -> Option<Segment> {
+    let (src, dst, tcp) = match version {
+        4 => {
+            let header_len = ((*data.first()? & 0x0f) as usize) * 4;
+            let total_len = u16::from_be_bytes(data.get(2..4)?.try_into().ok()?) as usize;
+            let fragment = u16::from_be_bytes(data.get(6..8)?.try_into().ok()?);
+            // skip fragments, JARM packets fit in one
+            if *data.get(9)? != 6 || fragment & 0x3fff != 0 || header_len < 20 {
+                return None;
+            }
+            let src: [u8; 4] = data.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = data.get(16..20)?.try_into().ok()?;
+            // #With segmentation offload the total length is 0, the frame has it all
+            let end = match total_len {
+                0 => data.len(),
+                len => len.clamp(header_len, data.len()),
+            };
+            (
+                IpAddr::V4(Ipv4Addr::from(src)),
+                IpAddr::V4(Ipv4Addr::from(dst)),
+                data.get(header_len..end)?,
+            )
+        }
+        6 => {
+            let payload_len = u16::from_be_bytes(data.get(4..6)?.try_into().ok()?) as usize;
+            let src: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+            let end = match payload_len {
+                0 => data.len(),
+                len => (40 + len).min(data.len()),
+            };
+            let mut next = *data.get(6)?;
+            let mut at = 40;
+            // walk the hop-by-hop, routing and destination options headers
+            while matches!(next, 0 | 43 | 60) {
+                next = *data.get(at)?;
+                at += (*data.get(at + 1)? as usize + 1) * 8;
+            }
+            if next != 6 {
+                return None;
+            }
+            (
+                IpAddr::V6(Ipv6Addr::from(src)),
+                IpAddr::V6(Ipv6Addr::from(dst)),
+                data.get(at..end)?,
+            )
+        }
+        _ => return None,
+    };
+    let port = |at: usize| Some(u16::from_be_bytes(tcp.get(at..at + 2)?.try_into().ok()?));
+    let header_len = ((*tcp.get(12)? >> 4) as usize) * 4;
+    Some(Segment {
+        timestamp,
+        src: SocketAddr::new(src, port(0)?),
+        dst: SocketAddr::new(dst, port(2)?),
+        seq: u32::from_be_bytes(tcp.get(4..8)?.try_into().ok()?),
+        flags: *tcp.get(13)?,
+        payload: tcp.get(header_len.max(20)..)?.to_vec(),
+    })
+}
@@ -0,0 +1,951 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        CipherList, ExtensionOrder, Order, ProbeSet, ProbeSetError, ProbeSpec, SupportedVersions,
+        Version,
+    };
+    use rand::rngs::mock::StepRng;
+    use std::collections::BTreeMap;
+
+    #[derive(serde::Deserialize)]
+    struct Golden {
+        host: String,
+        probes: BTreeMap<String, String>,
+    }
+
+    // The ClientHellos of jarm.py with its randomness replaced by a counter,
+    // see tests/golden/client_hellos.py
+    #[test]
+    fn client_hellos_match_reference() {
+        let golden: Golden =
+            toml::from_str(include_str!("../tests/golden/client_hellos.toml")).unwrap();
+        let set = ProbeSet::jarm();
+        assert_eq!(golden.probes.len(), set.probes().len());
+        for probe in set.probes() {
+            let packet = probe.build_packet(Some(&golden.host), &mut StepRng::new(0, 1));
+            assert_eq!(
+                hex::encode(packet),
+                golden.probes[&probe.name],
+                "{}",
+                probe.name
+            );
+        }
+    }
+
+    // The protocol names in a probe's ALPN extension, in the order it sends them
+    fn alpn_protocols(probe: &ProbeSpec) -> Vec<String> {
+        let extension = probe.apln();
+        let mut protocols = Vec::new();
+        let mut rest = &extension[6..];
+        while let Some((&length, tail)) = rest.split_first() {
+            let (protocol, tail) = tail.split_at(length as usize);
+            protocols.push(String::from_utf8(protocol.to_vec()).unwrap());
+            rest = tail;
+        }
+        protocols
+    }
+
+    #[test]
+    fn alpn_order() {
+        let set = ProbeSet::jarm();
+        let probe = |name: &str| set.probes().iter().find(|p| p.name == name).unwrap();
+        assert_eq!(
+            alpn_protocols(probe("tls1_2_reverse")),
+            ["http/0.9", "http/1.0", "http/1.1", "spdy/1", "spdy/2", "spdy/3", "h2", "h2c", "hq"]
+        );
+        // spdy/3 and h2 are reordered like any other two protocols
+        assert_eq!(
+            alpn_protocols(probe("tls1_2_forward")),
+            ["hq", "h2c", "h2", "spdy/3", "spdy/2", "spdy/1", "http/1.1", "http/1.0", "http/0.9"]
+        );
+        assert_eq!(
+            alpn_protocols(probe("tls1_2_middle_out")),
+            ["hq", "h2c", "spdy/3", "spdy/2", "spdy/1", "http/1.0", "http/0.9"]
+        );
+    }
+
+    #[test]
+    fn python_style_and_named_fields() {
+        let set = ProbeSet::from_toml(
+            r#"
+            name = "extra"
+
+            [[probe]]
+            name = "tls1_2_forward"
+            options = ["TLS_1.2", "ALL", "FORWARD", "NO_GREASE", "APLN", "1.2_SUPPORT", "REVERSE"]
+
+            [[probe]]
+            name = "tls1_3_custom"
+            version = "TLS_1.3"
+            cipher_order = "MIDDLE_OUT"
+            grease = true
+            supported_versions = "1.3_SUPPORT"
+            extension_order = "REVERSE"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(set.name(), "extra");
+        assert_eq!(set.probes()[0], ProbeSet::jarm().probes()[0]);
+        assert_eq!(
+            set.probes()[1],
+            ProbeSpec {
+                name: "tls1_3_custom".to_string(),
+                version: Version::Tls1_3,
+                cipher_list: CipherList::All,
+                cipher_order: Order::MiddleOut,
+                grease: true,
+                rare_alpn: false,
+                supported_versions: SupportedVersions::Tls1_3,
+                extension_order: ExtensionOrder::Reverse,
+            }
+        );
+        // probes are written back as jarm.py options
+        let json = serde_json::to_string(&set).unwrap();
+        assert!(json.contains(
+            r#""options":["TLS_1.3","ALL","MIDDLE_OUT","GREASE","APLN","1.3_SUPPORT","REVERSE"]"#
+        ));
+        assert_eq!(ProbeSet::from_json(&json).unwrap(), set);
+        assert_eq!(ProbeSet::default(), ProbeSet::jarm());
+        assert_eq!(ProbeSet::jarm().name(), super::JARM_NAME);
+        assert_eq!(ProbeSet::jarm().probes().len(), 10);
+    }
+
+    #[test]
+    fn errors() {
+        let parse = |probes: &str| ProbeSet::from_toml(&format!("name = \"x\"\n{}", probes));
+        for probes in [
+            "",
+            "[[probe]]\nname = \"a\"\noptions = [\"TLS_1.2\", \"ALL\"]\n",
+            "[[probe]]\nname = \"a\"\noptions = [\"TLS_1.2\", \"ALL\", \"SIDEWAYS\", \"GREASE\", \"APLN\", \"NO_SUPPORT\", \"FORWARD\"]\n",
+            "[[probe]]\nname = \"a\"\ncipher_list = \"ALL\"\n",
+            // extension orders other than forward and reverse are not supported
+            "[[probe]]\nname = \"a\"\noptions = [\"TLS_1.2\", \"ALL\", \"FORWARD\", \"GREASE\", \"APLN\", \"NO_SUPPORT\", \"MIDDLE_OUT\"]\n",
+            "[[probe]]\nname = \"a\"\nversion = \"TLS_1.2\"\nextension_order = \"TOP_HALF\"\n",
+            "[[probe]]\nname = \"a\"\nversion = \"TLS_1.2\"\noptions = [\"TLS_1.2\", \"ALL\", \"FORWARD\", \"GREASE\", \"APLN\", \"NO_SUPPORT\", \"FORWARD\"]\n",
+            "[[probe]]\nname = \"a\"\nversion = \"TLS_1.2\"\n[[probe]]\nname = \"a\"\nversion = \"TLS_1.3\"\n",
+        ] {
+            assert!(matches!(parse(probes), Err(ProbeSetError::Parse(_))), "{}", probes);
+        }
+        assert!(matches!(
+            ProbeSet::load("probes.yaml"),
+            Err(ProbeSetError::UnknownFormat(_))
+        ));
+    }
+}
+
+const STANDARD: &str = include_str!("../data/probes.toml");
+// The name of the standard set in that file
+pub(crate) const JARM_NAME: &str = "jarm";
+
+/// One probe, the ClientHello options of a line of jarm.py like
+/// `["TLS_1.2", "ALL", "FORWARD", "NO_GREASE", "APLN", "1.2_SUPPORT", "REVERSE"]`.
+///
+/// In files a probe has a `name` and either those seven `options` or the
+/// fields below, where only `version` is required.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "SpecFile", into = "SpecFile")]
+pub struct ProbeSpec {
+    /// Names the probe in results, e.g. `tls1_2_forward`
+    pub name: String,
+    pub version: Version,
+    pub cipher_list: CipherList,
+    pub cipher_order: Order,
+    /// Start the cipher suites and extensions with a GREASE value, `GREASE` or `NO_GREASE`
+    pub grease: bool,
+    /// Offer the rare ALPN list without http/1.1 and h2, `RARE_APLN` or `APLN`
+    pub rare_alpn: bool,
+    pub supported_versions: SupportedVersions,
+    /// Order of the ALPN and supported versions lists
+    pub extension_order: ExtensionOrder,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Grease {
+    #[serde(rename = "GREASE")]
+    Grease,
+    #[serde(rename = "NO_GREASE")]
+    NoGrease,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Alpn {
+    #[serde(rename = "APLN")]
+    Alpn,
+    #[serde(rename = "RARE_APLN")]
+    RareAlpn,
+}
+
+type Options = (
+    Version,
+    CipherList,
+    Order,
+    Grease,
+    Alpn,
+    SupportedVersions,
+    ExtensionOrder,
+);
+
+// A probe in a file, with the options of jarm.py or named fields
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpecFile {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    options: Option<Options>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<Version>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cipher_list: Option<CipherList>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cipher_order: Option<Order>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    grease: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rare_alpn: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    supported_versions: Option<SupportedVersions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extension_order: Option<ExtensionOrder>,
+}
+
+impl TryFrom<SpecFile> for ProbeSpec {
+    type Error = String;
+
+    fn try_from(file: SpecFile) -> Result<Self, Self::Error> {
+        let named = file.version.is_some()
+            || file.cipher_list.is_some()
+            || file.cipher_order.is_some()
+            || file.grease.is_some()
+            || file.rare_alpn.is_some()
+            || file.supported_versions.is_some()
+            || file.extension_order.is_some();
+        match (file.options, file.version) {
+            (Some(_), _) if named => Err(format!(
+                "probe {} has both options and named fields",
+                file.name
+            )),
+            (
+                Some((version, cipher_list, cipher_order, grease, alpn, supported, extensions)),
+                _,
+            ) => Ok(ProbeSpec {
+                name: file.name,
+                version,
+                cipher_list,
+                cipher_order,
+                grease: matches!(grease, Grease::Grease),
+                rare_alpn: matches!(alpn, Alpn::RareAlpn),
+                supported_versions: supported,
+                extension_order: extensions,
+            }),
+            (None, Some(version)) => Ok(ProbeSpec {
+                name: file.name,
+                version,
+                cipher_list: file.cipher_list.unwrap_or(CipherList::All),
+                cipher_order: file.cipher_order.unwrap_or(Order::Forward),
+                grease: file.grease.unwrap_or(false),
+                rare_alpn: file.rare_alpn.unwrap_or(false),
+                supported_versions: file
+                    .supported_versions
+                    .unwrap_or(SupportedVersions::NoSupport),
+                extension_order: file.extension_order.unwrap_or(ExtensionOrder::Forward),
+            }),
+            (None, None) => Err(format!("probe {} needs options or a version", file.name)),
+        }
+    }
+}
+
+impl From<ProbeSpec> for SpecFile {
+    fn from(spec: ProbeSpec) -> Self {
+        let grease = match spec.grease {
+            true => Grease::Grease,
+            false => Grease::NoGrease,
+        };
+        let alpn = match spec.rare_alpn {
+            true => Alpn::RareAlpn,
+            false => Alpn::Alpn,
+        };
+        SpecFile {
+            name: spec.name,
+            options: Some((
+                spec.version,
+                spec.cipher_list,
+                spec.cipher_order,
+                grease,
+                alpn,
+                spec.supported_versions,
+                spec.extension_order,
+            )),
+            version: None,
+            cipher_list: None,
+            cipher_order: None,
+            grease: None,
+            rare_alpn: None,
+            supported_versions: None,
+            extension_order: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeSetError {
+    Io(String),
+    /// The file is not valid TOML or JSON, or a probe is invalid
+    Parse(String),
+    /// The file extension is not `.toml` or `.json`
+    UnknownFormat(String),
+}
+
+impl fmt::Display for ProbeSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeSetError::Io(e) => write!(f, "io error: {}", e),
+            ProbeSetError::Parse(e) => write!(f, "invalid probe set: {}", e),
+            ProbeSetError::UnknownFormat(path) => {
+                write!(f, "{} is not a .toml or .json file", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProbeSetError {}
+
+/// The probes a scanner sends, in fingerprint order.
+///
+/// The default is the ten probes of JARM, named `jarm`. Other sets make
+/// fingerprints of 3 characters per probe plus the extension hash, and
+/// results carry the set's name in [`ScanResult::probe_set`](crate::ScanResult::probe_set).
+///
+/// ```
+/// use jarm::{ProbeSet, Scanner};
+///
+/// let probes = ProbeSet::from_toml(r#"
+///     name = "tls1_3_only"
+///
+///     [[probe]]
+///     name = "tls1_3_forward"
+///     options = ["TLS_1.3", "ALL", "FORWARD", "NO_GREASE", "APLN", "1.3_SUPPORT", "REVERSE"]
+/// "#).unwrap();
+/// let scanner = Scanner::builder("127.0.0.1", 443).probes(probes).build().unwrap();
+/// assert_eq!(scanner.client_hellos().len(), 1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeSet {
+    name: String,
+    #[serde(rename = "probe")]
+    probes: Vec<ProbeSpec>,
+}
+
+impl Default for ProbeSet {
+    fn default() -> Self {
+        ProbeSet::jarm()
+    }
+}
+
+impl ProbeSet {
+    /// The ten probes of JARM.
+    pub fn jarm() -> Self {
+        ProbeSet::from_toml(STANDARD).expect("standard probe set is valid")
+    }
+    /// At least one probe, with unique names.
+    pub fn new(name: impl Into<String>, probes: Vec<ProbeSpec>) -> Result<Self, ProbeSetError> {
+        if probes.is_empty() {
+            return Err(ProbeSetError::Parse("no probes".to_string()));
+        }
+        for (index, probe) in probes.iter().enumerate() {
+            if probes[..index].iter().any(|p| p.name == probe.name) {
+                return Err(ProbeSetError::Parse(format!(
+                    "probe {} is defined twice",
+                    probe.name
+                )));
+            }
+        }
+        Ok(ProbeSet {
+            name: name.into(),
+            probes,
+        })
+    }
+    /// Load a `.toml` or `.json` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProbeSetError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let parse = match extension.as_deref() {
+            Some("toml") => ProbeSet::from_toml,
+            Some("json") => ProbeSet::from_json,
+            _ => return Err(ProbeSetError::UnknownFormat(path.display().to_string())),
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| ProbeSetError::Io(e.to_string()))?;
+        parse(&text)
+    }
+    /// A `name` and `[[probe]]` tables, see `data/probes.toml`.
+    pub fn from_toml(text: &str) -> Result<Self, ProbeSetError> {
+        let set: ProbeSet =
+            toml::from_str(text).map_err(|e| ProbeSetError::Parse(e.to_string()))?;
+        ProbeSet::new(set.name, set.probes)
+    }
+    /// `{"name": ..., "probe": [...]}`, like the TOML.
+    pub fn from_json(text: &str) -> Result<Self, ProbeSetError> {
+        let set: ProbeSet =
+            serde_json::from_str(text).map_err(|e| ProbeSetError::Parse(e.to_string()))?;
+        ProbeSet::new(set.name, set.probes)
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn probes(&self) -> &[ProbeSpec] {
+        &self.probes
+    }
+}
+
+// #Randomly choose one of the 16 grease values, 0a0a to fafa
+fn choose_grease<R: Rng + ?Sized>(rng: &mut R) -> Vec<u8> {
+    let h: u8 = rng.gen_range(0..16);
+    let grease = vec![h * 16 + 10, h * 16 + 10];
+    grease
+}
+
+fn random_bytes<R: Rng + ?Sized>(rng: &mut R) -> Vec<u8> {
+    rng.gen::<[u8; 32]>().to_vec()
+}
+
+/// The record and ClientHello version of a probe, `TLS_1.3` sends 1.2 and
+/// offers 1.3 in the supported versions extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Version {
+    #[serde(rename = "SSLv3")]
+    Ssl3,
+    #[serde(rename = "TLS_1")]
+    Tls1_0,
+    #[serde(rename = "TLS_1.1")]
+    Tls1_1,
+    #[serde(rename = "TLS_1.2")]
+    Tls1_2,
+    #[serde(rename = "TLS_1.3")]
+    Tls1_3,
+}
+
+impl Version {
+    // #Version Check
+    fn hello_payload(&self) -> (Vec<u8>, Vec<u8>) {
+        let mut client_hello: Vec<u8> = Vec::new();
+        let mut payload = b"\x16".to_vec();
+        match self {
+            Version::Ssl3 => {
+                payload.extend(b"\x03\x00");
+                client_hello.extend(b"\x03\x00");
+            }
+            Version::Tls1_0 => {
+                payload.extend(b"\x03\x01");
+                client_hello.extend(b"\x03\x01");
+            }
+            Version::Tls1_1 => {
+                payload.extend(b"\x03\x02");
+                client_hello.extend(b"\x03\x02");
+            }
+            Version::Tls1_2 => {
+                payload.extend(b"\x03\x03");
+                client_hello.extend(b"\x03\x03");
+            }
+            Version::Tls1_3 => {
+                payload.extend(b"\x03\x01");
+                client_hello.extend(b"\x03\x03");
+            }
+        }
+        (client_hello, payload)
+    }
+}
+
+/// The cipher suites a probe offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CipherList {
+    #[serde(rename = "ALL")]
+    All,
+    /// Every suite but the TLS 1.3 ones
+    #[serde(rename = "NO1.3")]
+    No1_3,
+}
+
+impl CipherList {
+    fn lists(&self) -> Vec<Vec<u8>> {
+        match self {
+            CipherList::All => {
+                vec![
+                    b"\x00\x16".to_vec(),
+                    b"\x00\x33".to_vec(),
+                    b"\x00\x67".to_vec(),
+                    b"\xc0\x9e".to_vec(),
+                    b"\xc0\xa2".to_vec(),
+                    b"\x00\x9e".to_vec(),
+                    b"\x00\x39".to_vec(),
+                    b"\x00\x6b".to_vec(),
+                    b"\xc0\x9f".to_vec(),
+                    b"\xc0\xa3".to_vec(),
+                    b"\x00\x9f".to_vec(),
+                    b"\x00\x45".to_vec(),
+                    b"\x00\xbe".to_vec(),
+                    b"\x00\x88".to_vec(),
+                    b"\x00\xc4".to_vec(),
+                    b"\x00\x9a".to_vec(),
+                    b"\xc0\x08".to_vec(),
+                    b"\xc0\x09".to_vec(),
+                    b"\xc0\x23".to_vec(),
+                    b"\xc0\xac".to_vec(),
+                    b"\xc0\xae".to_vec(),
+                    b"\xc0\x2b".to_vec(),
+                    b"\xc0\x0a".to_vec(),
+                    b"\xc0\x24".to_vec(),
+                    b"\xc0\xad".to_vec(),
+                    b"\xc0\xaf".to_vec(),
+                    b"\xc0\x2c".to_vec(),
+                    b"\xc0\x72".to_vec(),
+                    b"\xc0\x73".to_vec(),
+                    b"\xcc\xa9".to_vec(),
+                    b"\x13\x02".to_vec(),
+                    b"\x13\x01".to_vec(),
+                    b"\xcc\x14".to_vec(),
+                    b"\xc0\x07".to_vec(),
+                    b"\xc0\x12".to_vec(),
+                    b"\xc0\x13".to_vec(),
+                    b"\xc0\x27".to_vec(),
+                    b"\xc0\x2f".to_vec(),
+                    b"\xc0\x14".to_vec(),
+                    b"\xc0\x28".to_vec(),
+                    b"\xc0\x30".to_vec(),
+                    b"\xc0\x60".to_vec(),
+                    b"\xc0\x61".to_vec(),
+                    b"\xc0\x76".to_vec(),
+                    b"\xc0\x77".to_vec(),
+                    b"\xcc\xa8".to_vec(),
+                    b"\x13\x05".to_vec(),
+                    b"\x13\x04".to_vec(),
+                    b"\x13\x03".to_vec(),
+                    b"\xcc\x13".to_vec(),
+                    b"\xc0\x11".to_vec(),
+                    b"\x00\x0a".to_vec(),
+                    b"\x00\x2f".to_vec(),
+                    b"\x00\x3c".to_vec(),
+                    b"\xc0\x9c".to_vec(),
+                    b"\xc0\xa0".to_vec(),
+                    b"\x00\x9c".to_vec(),
+                    b"\x00\x35".to_vec(),
+                    b"\x00\x3d".to_vec(),
+                    b"\xc0\x9d".to_vec(),
+                    b"\xc0\xa1".to_vec(),
+                    b"\x00\x9d".to_vec(),
+                    b"\x00\x41".to_vec(),
+                    b"\x00\xba".to_vec(),
+                    b"\x00\x84".to_vec(),
+                    b"\x00\xc0".to_vec(),
+                    b"\x00\x07".to_vec(),
+                    b"\x00\x04".to_vec(),
+                    b"\x00\x05".to_vec(),
+                ]
+            }
+            CipherList::No1_3 => {
+                vec![
+                    b"\x00\x16".to_vec(),
+                    b"\x00\x33".to_vec(),
+                    b"\x00\x67".to_vec(),
+                    b"\xc0\x9e".to_vec(),
+                    b"\xc0\xa2".to_vec(),
+                    b"\x00\x9e".to_vec(),
+                    b"\x00\x39".to_vec(),
+                    b"\x00\x6b".to_vec(),
+                    b"\xc0\x9f".to_vec(),
+                    b"\xc0\xa3".to_vec(),
+                    b"\x00\x9f".to_vec(),
+                    b"\x00\x45".to_vec(),
+                    b"\x00\xbe".to_vec(),
+                    b"\x00\x88".to_vec(),
+                    b"\x00\xc4".to_vec(),
+                    b"\x00\x9a".to_vec(),
+                    b"\xc0\x08".to_vec(),
+                    b"\xc0\x09".to_vec(),
+                    b"\xc0\x23".to_vec(),
+                    b"\xc0\xac".to_vec(),
+                    b"\xc0\xae".to_vec(),
+                    b"\xc0\x2b".to_vec(),
+                    b"\xc0\x0a".to_vec(),
+                    b"\xc0\x24".to_vec(),
+                    b"\xc0\xad".to_vec(),
+                    b"\xc0\xaf".to_vec(),
+                    b"\xc0\x2c".to_vec(),
+                    b"\xc0\x72".to_vec(),
+                    b"\xc0\x73".to_vec(),
+                    b"\xcc\xa9".to_vec(),
+                    b"\xcc\x14".to_vec(),
+                    b"\xc0\x07".to_vec(),
+                    b"\xc0\x12".to_vec(),
+                    b"\xc0\x13".to_vec(),
+                    b"\xc0\x27".to_vec(),
+                    b"\xc0\x2f".to_vec(),
+                    b"\xc0\x14".to_vec(),
+                    b"\xc0\x28".to_vec(),
+                    b"\xc0\x30".to_vec(),
+                    b"\xc0\x60".to_vec(),
+                    b"\xc0\x61".to_vec(),
+                    b"\xc0\x76".to_vec(),
+                    b"\xc0\x77".to_vec(),
+                    b"\xcc\xa8".to_vec(),
+                    b"\xcc\x13".to_vec(),
+                    b"\xc0\x11".to_vec(),
+                    b"\x00\x0a".to_vec(),
+                    b"\x00\x2f".to_vec(),
+                    b"\x00\x3c".to_vec(),
+                    b"\xc0\x9c".to_vec(),
+                    b"\xc0\xa0".to_vec(),
+                    b"\x00\x9c".to_vec(),
+                    b"\x00\x35".to_vec(),
+                    b"\x00\x3d".to_vec(),
+                    b"\xc0\x9d".to_vec(),
+                    b"\xc0\xa1".to_vec(),
+                    b"\x00\x9d".to_vec(),
+                    b"\x00\x41".to_vec(),
+                    b"\x00\xba".to_vec(),
+                    b"\x00\x84".to_vec(),
+                    b"\x00\xc0".to_vec(),
+                    b"\x00\x07".to_vec(),
+                    b"\x00\x04".to_vec(),
+                    b"\x00\x05".to_vec(),
+                ]
+            }
+        }
+    }
+}
+
+/// How a probe orders its cipher suites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Order {
+    #[serde(rename = "FORWARD")]
+    Forward,
+    #[serde(rename = "REVERSE")]
+    Reverse,
+    /// The second half reversed, after the middle entry of an odd list
+    #[serde(rename = "TOP_HALF")]
+    TopHalf,
+    /// The second half
+    #[serde(rename = "BOTTOM_HALF")]
+    BottomHalf,
+    /// Alternating outwards from the middle
+    #[serde(rename = "MIDDLE_OUT")]
+    MiddleOut,
+}
+
+impl Order {
+    #[allow(clippy::ptr_arg)]
+    fn cipher_mung(&self, ciphers: &mut Vec<Vec<u8>>) {
+        match self {
+            Order::Forward => {} // nothing to do
+            Order::Reverse => ciphers.reverse(),
+            Order::TopHalf => {
+                // Top half gets the middle cipher if needed
+                let middle_one = if ciphers.len() % 2 == 1 {
+                    Some(ciphers[ciphers.len() / 2].clone())
+                } else {
+                    None
+                };
+                ciphers.reverse();
+                let mut range_to_drain = 0..ciphers.len() / 2;
+                if ciphers.len() % 2 == 1 {
+                    // Also remove the middle one if the length is odd
+                    range_to_drain.end += 1;
+                }
+                ciphers.drain(range_to_drain);
+                if let Some(x) = middle_one {
+                    ciphers.insert(0, x);
+                }
+            }
+            Order::BottomHalf => {
+                let mut range_to_drain = 0..ciphers.len() / 2;
+                if ciphers.len() % 2 == 1 {
+                    // Also remove the middle one if the length is odd
+                    range_to_drain.end += 1;
+                }
+                ciphers.drain(range_to_drain);
+            }
+            Order::MiddleOut => {
+                let middle = ciphers.len() / 2;
+                let mut output = Vec::new();
+                if ciphers.len() % 2 == 1 {
+                    // output.append(ciphers[middle])
+                    output.push(ciphers[middle].clone());
+
+                    for i in 1..middle + 1 {
+                        output.push(ciphers[middle + i].clone());
+                        output.push(ciphers[middle - i].clone());
+                    }
+                } else {
+                    for i in 1..middle + 1 {
+                        output.push(ciphers[middle - 1 + i].clone());
+                        output.push(ciphers[middle - i].clone());
+                    }
+                }
+                *ciphers = output;
+            }
+        }
+    }
+}
+
+/// How a probe orders its ALPN and supported versions lists.
+///
+/// jarm.py takes any [`Order`] here, this crate deliberately keeps to the
+/// forward and reverse orders that the JARM probes use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExtensionOrder {
+    #[serde(rename = "FORWARD")]
+    Forward,
+    #[serde(rename = "REVERSE")]
+    Reverse,
+}
+
+impl ExtensionOrder {
+    #[allow(clippy::ptr_arg)]
+    fn cipher_mung(&self, ciphers: &mut Vec<Vec<u8>>) {
+        match self {
+            ExtensionOrder::Forward => {} // nothing to do
+            ExtensionOrder::Reverse => ciphers.reverse(),
+        }
+    }
+}
+
+/// The supported versions extension of a probe.
+///
+/// `TLS_1.3` probes always send it, up to 1.3 unless it is `1.2_SUPPORT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SupportedVersions {
+    /// 1.0 to 1.2
+    #[serde(rename = "1.2_SUPPORT")]
+    Tls1_2,
+    /// 1.0 to 1.3
+    #[serde(rename = "1.3_SUPPORT")]
+    Tls1_3,
+    #[serde(rename = "NO_SUPPORT")]
+    NoSupport,
+}
+
+impl SupportedVersions {
+    fn lists(&self) -> Vec<Vec<u8>> {
+        match self {
+            SupportedVersions::Tls1_2 => {
+                vec![
+                    b"\x03\x01".to_vec(),
+                    b"\x03\x02".to_vec(),
+                    b"\x03\x03".to_vec(),
+                ]
+            }
+            _ => {
+                vec![
+                    b"\x03\x01".to_vec(),
+                    b"\x03\x02".to_vec(),
+                    b"\x03\x03".to_vec(),
+                    b"\x03\x04".to_vec(),
+                ]
+            }
+        }
+    }
+}
+
+fn pack_as_unsigned_char(n: usize) -> u8 {
+    if n >= 256 {
+        panic!("Can't pack_as_unsigned_char {:?} as it is over 255", n)
+    }
+    n as u8
+}
+
+fn pack_as_unsigned_short(n: usize) -> Vec<u8> {
+    vec![(n >> 8) as u8, n as u8]
+}
+
+impl ProbeSpec {
+    // #The random bytes and grease values are drawn from `rng` in the order of jarm.py
+    pub(crate) fn build_packet<R: Rng + ?Sized>(&self, host: Option<&str>, rng: &mut R) -> Vec<u8> {
+        let (mut client_hello, mut payload) = self.version.hello_payload();
+
+        client_hello.extend(random_bytes(rng));
+
+        let session_id = random_bytes(rng);
+        let session_id_length = pack_as_unsigned_char(session_id.len());
+        client_hello.push(session_id_length);
+        client_hello.extend(session_id);
+
+        let cipher_choice = self.get_ciphers(rng);
+
+        let client_suites_length = pack_as_unsigned_short(cipher_choice.len());
+        client_hello.extend(client_suites_length);
+        client_hello.extend(cipher_choice);
+        client_hello.push(b'\x01'); // cipher methods
+        client_hello.push(b'\x00'); // compression_methods
+
+        client_hello.extend(self.get_extensions(host, rng));
+
+        // Finish packet assembly
+        let mut inner_length = b"\x00".to_vec();
+        inner_length.extend(pack_as_unsigned_short(client_hello.len()));
+        let mut handshake_protocol = b"\x01".to_vec();
+        handshake_protocol.extend(inner_length);
+        handshake_protocol.extend(client_hello);
+        let outer_length = pack_as_unsigned_short(handshake_protocol.len());
+        payload.extend(outer_length);
+        payload.extend(handshake_protocol);
+        payload
+    }
+    fn get_ciphers<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<u8> {
+        let mut selected_ciphers = Vec::new();
+        let mut lists = self.cipher_list.lists();
+        self.cipher_order.cipher_mung(&mut lists);
+        if self.grease {
+            lists.insert(0, choose_grease(rng));
+        }
+        for x in lists {
+            selected_ciphers.extend(x);
+        }
+        selected_ciphers
+    }
+    fn get_extensions<R: Rng + ?Sized>(&self, host: Option<&str>, rng: &mut R) -> Vec<u8> {
+        let mut extension_bytes = Vec::new();
+        let mut all_extensions = Vec::new();
+        if self.grease {
+            all_extensions.extend(choose_grease(rng));
+            all_extensions.extend(b"\x00\x00");
+        }
+
+        if let Some(host) = host {
+            all_extensions.extend(self.extension_server_name(host));
+        }
+
+        // Other extensions
+        let extended_master_secret = b"\x00\x17\x00\x00";
+        all_extensions.extend(extended_master_secret);
+        let max_fragment_length = b"\x00\x01\x00\x01\x01";
+        all_extensions.extend(max_fragment_length);
+        let renegotiation_info = b"\xff\x01\x00\x01\x00";
+        all_extensions.extend(renegotiation_info);
+        let supported_groups = b"\x00\x0a\x00\x0a\x00\x08\x00\x1d\x00\x17\x00\x18\x00\x19";
+        all_extensions.extend(supported_groups);
+        let ec_point_formats = b"\x00\x0b\x00\x02\x01\x00";
+        all_extensions.extend(ec_point_formats);
+        let session_ticket = b"\x00\x23\x00\x00";
+        all_extensions.extend(session_ticket);
+
+        // Application Layer Protocol Negotiation extension
+        all_extensions.extend(self.apln());
+        let signature_algorithms = b"\x00\x0d\x00\x14\x00\x12\x04\x03\x08\x04\x04\x01\x05\x03\x08\x05\x05\x01\x08\x06\x06\x01\x02\x01";
+        all_extensions.extend(signature_algorithms);
+
+        // Key share extension
+        all_extensions.extend(self.key_share(rng));
+        let psk_key_exchange_modes = b"\x00\x2d\x00\x02\x01\x01";
+        all_extensions.extend(psk_key_exchange_modes);
+
+        if self.version == Version::Tls1_3 || self.supported_versions == SupportedVersions::Tls1_2 {
+            all_extensions.extend(self.versions_extension(rng));
+        }
+
+        extension_bytes.extend(pack_as_unsigned_short(all_extensions.len()));
+        extension_bytes.extend(all_extensions);
+        extension_bytes
+    }
+    fn extension_server_name(&self, host: &str) -> Vec<u8> {
+        let mut ext_sni = b"\x00\x00".to_vec();
+        let host_length = host.len();
+        let ext_sni_length = host_length + 5;
+        ext_sni.extend(pack_as_unsigned_short(ext_sni_length));
+
+        let ext_sni_length2 = host_length + 3;
+        ext_sni.extend(pack_as_unsigned_short(ext_sni_length2));
+        ext_sni.push(b'\x00');
+
+        let ext_sni_length3 = host_length;
+        ext_sni.extend(pack_as_unsigned_short(ext_sni_length3));
+
+        ext_sni.extend(host.to_string().bytes());
+        ext_sni
+    }
+    fn versions_extension<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<u8> {
+        let mut tls = self.supported_versions.lists();
+        self.extension_order.cipher_mung(&mut tls);
+        // Assemble the extension
+        let mut ext = b"\x00\x2b".to_vec();
+        let mut versions = if self.grease {
+            choose_grease(rng)
+        } else {
+            Vec::new()
+        };
+
+        for version in tls {
+            versions.extend(version);
+        }
+        let second_length = versions.len();
+        let first_length = second_length + 1;
+        ext.extend(pack_as_unsigned_short(first_length));
+        ext.push(pack_as_unsigned_char(second_length));
+        ext.extend(versions);
+        ext
+    }
+    fn apln(&self) -> Vec<u8> {
+        let mut ext = b"\x00\x10".to_vec();
+        let mut apln: Vec<Vec<u8>> = if self.rare_alpn {
+            vec![
+                b"\x08\x68\x74\x74\x70\x2f\x30\x2e\x39".to_vec(),
+                b"\x08\x68\x74\x74\x70\x2f\x31\x2e\x30".to_vec(),
+                b"\x06\x73\x70\x64\x79\x2f\x31".to_vec(),
+                b"\x06\x73\x70\x64\x79\x2f\x32".to_vec(),
+                b"\x06\x73\x70\x64\x79\x2f\x33".to_vec(),
+                b"\x03\x68\x32\x63".to_vec(),
+                b"\x02\x68\x71".to_vec(),
+            ]
+        } else {
+            vec![
+                b"\x08\x68\x74\x74\x70\x2f\x30\x2e\x39".to_vec(),
+                b"\x08\x68\x74\x74\x70\x2f\x31\x2e\x30".to_vec(),
+                b"\x08\x68\x74\x74\x70\x2f\x31\x2e\x31".to_vec(),
+                b"\x06\x73\x70\x64\x79\x2f\x31".to_vec(),
+                b"\x06\x73\x70\x64\x79\x2f\x32".to_vec(),
+                b"\x06\x73\x70\x64\x79\x2f\x33".to_vec(),
+                b"\x02\x68\x32".to_vec(),
+                b"\x03\x68\x32\x63".to_vec(),
+                b"\x02\x68\x71".to_vec(),
+            ]
+        };
+        self.extension_order.cipher_mung(&mut apln);
+        // flatten the apln
+        let mut all_apln = Vec::new();
+        for x in apln {
+            all_apln.extend(x);
+        }
+
+        let second_length = all_apln.len();
+        let first_length = second_length + 2;
+        ext.extend(pack_as_unsigned_short(first_length));
+        ext.extend(pack_as_unsigned_short(second_length));
+        ext.extend(all_apln);
+        ext
+    }
+    fn key_share<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<u8> {
+        let mut ext = b"\x00\x33".to_vec();
+        let mut share_ext = if self.grease {
+            let mut grease_start = choose_grease(rng);
+            grease_start.extend(b"\x00\x01\x00");
+            grease_start
+        } else {
+            Vec::new()
+        };
+        share_ext.extend(b"\x00\x1d"); // group
+        share_ext.extend(b"\x00\x20"); // key_exchange_length
+        share_ext.extend(random_bytes(rng)); // key_exchange
+
+        let second_length = share_ext.len();
+        let first_length = second_length + 2;
+        ext.extend(pack_as_unsigned_short(first_length));
+        ext.extend(pack_as_unsigned_short(second_length));
+        ext.extend(share_ext);
+        ext
+    }
+}
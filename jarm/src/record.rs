@@ -0,0 +1,240 @@
+use crate::ServerHello;
+
+#[cfg(test)]
+mod tests {
+    use crate::record::Response;
+    use crate::test_support::server_hello;
+    use crate::Part;
+
+    // Split the handshake message of a single record into records of `size` bytes
+    fn fragment(record: &[u8], size: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for chunk in record[5..].chunks(size) {
+            out.extend(&record[..3]);
+            out.extend((chunk.len() as u16).to_be_bytes());
+            out.extend(chunk);
+        }
+        out
+    }
+
+    #[test]
+    fn single_record() {
+        let data = server_hello();
+        let mut response = Response::default();
+        response.push(&data[..40]);
+        assert!(!response.is_complete());
+        response.push(&data[40..]);
+        assert!(response.is_complete());
+        assert_eq!(response.data(), data);
+    }
+
+    #[test]
+    fn fragmented_records() {
+        let data = server_hello();
+        let fragmented = fragment(&data, 7);
+        let mut response = Response::default();
+        // feed it in tcp segments that don't line up with the records
+        for segment in fragmented.chunks(11) {
+            assert!(!response.is_complete());
+            response.push(segment);
+        }
+        assert!(response.is_complete());
+        assert_eq!(response.len(), fragmented.len());
+        assert_eq!(response.data(), data);
+        let part = Part::new(&response.data()).unwrap();
+        assert_eq!(part.cipher.as_deref(), Some("c02f"));
+    }
+
+    #[test]
+    fn change_cipher_spec_after_hello() {
+        let data = server_hello();
+        let mut segment = fragment(&data, 50);
+        segment.extend(b"\x14\x03\x03\x00\x01\x01");
+        let mut response = Response::default();
+        response.push(&segment);
+        assert!(response.is_complete());
+        assert_eq!(response.data(), data);
+        // the flight of a TLS 1.2 server hello ends there too
+        let mut flight = Response::new(true);
+        flight.push(&segment);
+        assert!(flight.is_complete());
+        assert_eq!(flight.data(), data);
+    }
+
+    #[test]
+    fn handshake_flight() {
+        let corpus: toml::Table =
+            toml::from_str(include_str!("../tests/golden/server_hellos.toml")).unwrap();
+        let hello = |name: &str| -> Vec<u8> {
+            let hello = corpus["hello"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|h| h["name"].as_str() == Some(name))
+                .unwrap();
+            hex::decode(hello["data"].as_str().unwrap()).unwrap()
+        };
+        let data = hello("tls1_2_certificate");
+        let fragmented = fragment(&data, 100);
+        let (mut hello_only, mut flight) = (Response::new(false), Response::new(true));
+        for record in fragmented.chunks(105) {
+            assert!(!flight.is_complete());
+            hello_only.push(record);
+            flight.push(record);
+        }
+        assert!(flight.is_complete());
+        assert_eq!(flight.data(), data);
+        assert!(hello_only.is_complete());
+        // a TLS 1.3 server hello ends the flight, the rest is encrypted
+        let mut response = Response::new(true);
+        response.push(&hello("tls1_3"));
+        assert!(response.is_complete());
+    }
+
+    #[test]
+    fn alert_and_garbage() {
+        let mut response = Response::default();
+        response.push(b"\x15\x03\x03\x00");
+        assert!(!response.is_complete());
+        response.push(b"\x02\x02\x28");
+        assert!(response.is_complete());
+        assert_eq!(response.data(), b"\x15\x03\x03\x00\x02\x02\x28");
+
+        let mut response = Response::default();
+        response.push(b"SSH-2.0-OpenSSH_9.0\r\n");
+        assert!(response.is_complete());
+    }
+}
+
+const HANDSHAKE: u8 = 22;
+const ALERT: u8 = 21;
+const SERVER_HELLO: u8 = 2;
+const SERVER_HELLO_DONE: u8 = 14;
+const SUPPORTED_VERSIONS_EXTENSION: u16 = 0x002b;
+const RECORD_HEADER_LEN: usize = 5;
+// TLSCiphertext.length can be at most 2^14 + 2048
+const MAX_RECORD_LEN: usize = 16384 + 2048;
+
+/// Reassembles the records of a server's response until the first
+/// handshake message or an alert is complete.
+///
+/// With `flight` it goes on to the ServerHelloDone after a TLS 1.2 server
+/// hello, to get the Certificate message in between.
+#[derive(Default)]
+pub(crate) struct Response {
+    flight: bool,
+    raw: Vec<u8>,
+    // offset of the next unparsed record in `raw`
+    parsed: usize,
+    record_version: [u8; 2],
+    handshake: Vec<u8>,
+    alert: Option<Vec<u8>>,
+    non_tls: bool,
+    // a record other than handshake or alert came after the handshake records
+    ended: bool,
+}
+
+impl Response {
+    pub(crate) fn new(flight: bool) -> Self {
+        Response {
+            flight,
+            ..Response::default()
+        }
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.raw.len()
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        self.raw.extend_from_slice(bytes);
+        while !self.non_tls && !self.ended && self.alert.is_none() {
+            let header = match self.raw.get(self.parsed..self.parsed + RECORD_HEADER_LEN) {
+                Some(header) => header,
+                None => break,
+            };
+            let content_type = header[0];
+            let length = u16::from_be_bytes([header[3], header[4]]) as usize;
+            // #Once handshake bytes arrived, e.g. a ChangeCipherSpec just ends the response
+            if !matches!(content_type, HANDSHAKE | ALERT) || length > MAX_RECORD_LEN {
+                match self.handshake.is_empty() {
+                    true => self.non_tls = true,
+                    false => self.ended = true,
+                }
+                break;
+            }
+            let start = self.parsed + RECORD_HEADER_LEN;
+            let fragment = match self.raw.get(start..start + length) {
+                Some(fragment) => fragment,
+                None => break,
+            };
+            if content_type == ALERT {
+                self.alert = Some(self.raw[self.parsed..start + length].to_vec());
+            } else {
+                if self.handshake.is_empty() {
+                    self.record_version = [header[1], header[2]];
+                }
+                self.handshake.extend_from_slice(fragment);
+            }
+            self.parsed = start + length;
+        }
+    }
+    /// The first handshake message is complete, or the flight when reading
+    /// it, an alert or another kind of record arrived or the response is not
+    /// TLS at all.
+    pub(crate) fn is_complete(&self) -> bool {
+        if self.non_tls || self.ended || self.alert.is_some() {
+            return true;
+        }
+        let messages = self.messages();
+        let first = match messages.first() {
+            Some(first) => first,
+            None => return false,
+        };
+        if !self.flight || first[0] != SERVER_HELLO || is_tls1_3(first) {
+            return true;
+        }
+        messages.iter().any(|m| m[0] == SERVER_HELLO_DONE)
+    }
+    // #The complete handshake messages so far
+    fn messages(&self) -> Vec<&[u8]> {
+        let mut messages = Vec::new();
+        let mut rest = &self.handshake[..];
+        while let Some(length) = rest.get(1..4) {
+            let length = 4 + u32::from_be_bytes([0, length[0], length[1], length[2]]) as usize;
+            match rest.get(..length) {
+                Some(message) => messages.push(message),
+                None => break,
+            }
+            rest = &rest[length..];
+        }
+        messages
+    }
+    /// The response as a single record, so it parses the same no matter how
+    /// the server split it up.
+    pub(crate) fn data(&self) -> Vec<u8> {
+        if self.non_tls || (self.handshake.is_empty() && self.alert.is_none()) {
+            return self.raw.clone();
+        }
+        if self.handshake.is_empty() {
+            return self.alert.clone().unwrap_or_default();
+        }
+        let length = self.handshake.len().min(u16::MAX as usize) as u16;
+        let mut data = vec![HANDSHAKE];
+        data.extend(self.record_version);
+        data.extend(length.to_be_bytes());
+        data.extend(&self.handshake[..length as usize]);
+        data
+    }
+}
+
+// #A TLS 1.3 server hello, the rest of the flight is encrypted
+fn is_tls1_3(message: &[u8]) -> bool {
+    ServerHello::parse_message(message).is_ok_and(|hello| {
+        hello
+            .extensions
+            .iter()
+            .any(|e| e.0 == SUPPORTED_VERSIONS_EXTENSION)
+    })
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+use crate::ja3::ja3s_hash;
+use crate::probe::JARM_NAME;
+use crate::{Certificate, Handshake, JarmError, JarmFingerprint, Match, Part};
+
+/// The server's answer to one of the JARM probes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeResult {
+    /// Probe name, e.g. `tls1_2_forward`
+    pub name: String,
+    /// Selected cipher suite as hex, e.g. `c02f`
+    pub cipher: Option<String>,
+    /// Version from the server hello as hex, e.g. `0303`
+    pub version: Option<String>,
+    /// Selected application layer protocol
+    pub alpn: Option<String>,
+    /// Extension types as hex, in the order the server sent them
+    pub extensions: Vec<String>,
+    /// JA3S of the server hello, e.g. `771,49199,65281-16-11-23`
+    #[serde(default)]
+    pub ja3s: Option<String>,
+    /// MD5 of the JA3S string
+    #[serde(default)]
+    pub ja3s_hash: Option<String>,
+    /// JA4S of the server hello, e.g. `t1204h2_c02f_594cf10ab965`
+    #[serde(default)]
+    pub ja4s: Option<String>,
+    /// JA4X of the leaf certificate, when a plaintext Certificate message followed the server hello
+    #[serde(default)]
+    pub ja4x: Option<String>,
+    /// Number of bytes received from the server
+    pub bytes_read: usize,
+    /// Why the probe got no server hello
+    pub error: Option<JarmError>,
+}
+
+impl ProbeResult {
+    pub(crate) fn new(
+        name: &str,
+        part: &Part,
+        bytes_read: usize,
+        error: Option<JarmError>,
+    ) -> Self {
+        ProbeResult {
+            name: name.to_string(),
+            cipher: part.cipher.clone(),
+            version: part.version.clone(),
+            alpn: part.extensions.version.clone().filter(|a| !a.is_empty()),
+            extensions: part.extensions.types.clone(),
+            ja3s: part.ja3s.clone(),
+            ja3s_hash: part.ja3s.as_deref().map(ja3s_hash),
+            ja4s: part.ja4s.clone(),
+            ja4x: part.ja4x.clone(),
+            bytes_read,
+            error,
+        }
+    }
+    /// Build the result of a probe from the server's raw response.
+    pub fn from_response(name: &str, data: &[u8]) -> Self {
+        match Part::new(data) {
+            Ok(part) => ProbeResult::new(name, &part, data.len(), None),
+            Err(e) => ProbeResult::new(name, &Part::default(), data.len(), Some(e)),
+        }
+    }
+}
+
+/// The fingerprint of a server together with the probe results it was computed from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanResult {
+    /// The address that was scanned, unless a proxy resolved the host
+    pub addr: Option<SocketAddr>,
+    pub fingerprint: JarmFingerprint,
+    /// Name of the [`ProbeSet`](crate::ProbeSet) behind the fingerprint, `jarm` for the standard probes
+    #[serde(default = "jarm_probe_set")]
+    pub probe_set: String,
+    pub probes: Vec<ProbeResult>,
+    /// The server's leaf certificate, from the first TLS 1.2 probe that read one,
+    /// see [`ScannerBuilder::certificates`](crate::ScannerBuilder::certificates)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<Certificate>,
+    /// The full handshake after the probes, see
+    /// [`ScannerBuilder::full_handshake`](crate::ScannerBuilder::full_handshake)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handshake: Option<Handshake>,
+    /// Labels from a [`FingerprintDb`](crate::FingerprintDb), filled in by its `annotate`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<Match>,
+}
+
+fn jarm_probe_set() -> String {
+    JARM_NAME.to_string()
+}
+
+impl ScanResult {
+    /// The error of the whole scan, set when every probe failed.
+    ///
+    /// The fingerprint is still computed, it is all zeros in that case.
+    pub fn error(&self) -> Option<&JarmError> {
+        if self.probes.iter().all(|p| p.error.is_some()) {
+            return self.probes.first().and_then(|p| p.error.as_ref());
+        }
+        None
+    }
+    /// No probe completed a TCP connect, the port is closed or filtered.
+    pub fn is_closed(&self) -> bool {
+        !self.probes.is_empty()
+            && self.probes.iter().all(|p| {
+                matches!(
+                    p.error,
+                    Some(JarmError::ConnectRefused | JarmError::ConnectTimeout)
+                )
+            })
+    }
+}
@@ -0,0 +1,431 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::JarmError;
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{listen, server_hello};
+    use crate::{JarmError, Scanner, StartTls};
+    use std::io::{Read, Write};
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+
+    type Script = &'static [(&'static [u8], &'static [u8])];
+
+    // Play the server side of an upgrade: check each client line, send each reply,
+    // then answer the client hello. An empty client line is the greeting.
+    fn server(script: Script) -> SocketAddr {
+        listen(move |_, mut stream| {
+            for (client, reply) in script {
+                let mut line = vec![0; client.len()];
+                stream.read_exact(&mut line).unwrap();
+                assert_eq!(
+                    String::from_utf8_lossy(&line),
+                    String::from_utf8_lossy(client)
+                );
+                stream.write_all(reply).unwrap();
+            }
+            let mut hello = [0; 5];
+            if stream.read_exact(&mut hello).is_ok() && hello[0] == 0x16 {
+                let _ = stream.write_all(&server_hello());
+            }
+        })
+    }
+
+    fn scan(protocol: StartTls, addr: SocketAddr) -> crate::ScanResult {
+        Scanner::builder("mail.example", addr.port())
+            .addr(addr)
+            .starttls(protocol)
+            .build()
+            .unwrap()
+            .scan()
+    }
+
+    #[test]
+    fn upgrades() {
+        let scripts: [(StartTls, Script); 7] = [
+            (
+                StartTls::Smtp,
+                &[
+                    (b"", b"220-mail.example ESMTP\r\n220 ready\r\n"),
+                    (b"EHLO jarm\r\n", b"250-mail.example\r\n250 STARTTLS\r\n"),
+                    (b"STARTTLS\r\n", b"220 go ahead\r\n"),
+                ],
+            ),
+            (
+                StartTls::Imap,
+                &[
+                    (b"", b"* OK IMAP4rev1 ready\r\n"),
+                    (b"a001 STARTTLS\r\n", b"* CAPABILITY IMAP4rev1\r\na001 OK begin\r\n"),
+                ],
+            ),
+            (
+                StartTls::Pop3,
+                &[(b"", b"+OK POP3 ready\r\n"), (b"STLS\r\n", b"+OK begin\r\n")],
+            ),
+            (
+                StartTls::Ftp,
+                &[(b"", b"220 FTP ready\r\n"), (b"AUTH TLS\r\n", b"234 AUTH TLS OK\r\n")],
+            ),
+            (
+                StartTls::Xmpp,
+                &[
+                    (
+                        b"<?xml version='1.0'?><stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' to='mail.example' version='1.0'>",
+                        b"<?xml version='1.0'?><stream:stream from='mail.example' version='1.0'><stream:features><starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'><required/></starttls></stream:features>",
+                    ),
+                    (
+                        b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>",
+                        b"<proceed xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>",
+                    ),
+                ],
+            ),
+            (
+                StartTls::Ldap,
+                &[(
+                    b"\x30\x1d\x02\x01\x01\x77\x18\x80\x161.3.6.1.4.1.1466.20037",
+                    b"\x30\x0c\x02\x01\x01\x78\x07\x0a\x01\x00\x04\x00\x04\x00",
+                )],
+            ),
+            (
+                StartTls::Postgres,
+                &[(b"\x00\x00\x00\x08\x04\xd2\x16\x2f", b"S")],
+            ),
+        ];
+        for (protocol, script) in scripts {
+            let result = scan(protocol, server(script));
+            assert_eq!(result.error(), None, "{}", protocol);
+            assert!(
+                result.probes.iter().all(|p| p.error.is_none()),
+                "{}",
+                protocol
+            );
+        }
+    }
+
+    #[test]
+    fn refused() {
+        let addr = server(&[(b"", b"+OK ready\r\n"), (b"STLS\r\n", b"-ERR not here\r\n")]);
+        let result = scan(StartTls::Pop3, addr);
+        assert_eq!(
+            result.error(),
+            Some(&JarmError::StartTls("-ERR not here".to_string()))
+        );
+        let addr = server(&[(b"\x00\x00\x00\x08\x04\xd2\x16\x2f", b"N")]);
+        assert!(matches!(
+            scan(StartTls::Postgres, addr).error(),
+            Some(JarmError::StartTls(_))
+        ));
+    }
+
+    #[test]
+    fn names() {
+        for protocol in StartTls::ALL {
+            assert_eq!(StartTls::from_str(&protocol.to_string()), Ok(protocol));
+        }
+        assert_eq!(StartTls::from_str("postgresql"), Ok(StartTls::Postgres));
+        assert!(StartTls::from_str("telnet").is_err());
+        assert_eq!(StartTls::Smtp.default_port(), 25);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_upgrade() {
+        let addr = server(&[(b"", b"220 ready\r\n"), (b"AUTH TLS\r\n", b"234 ok\r\n")]);
+        let scanner = Scanner::builder("ftp.example", addr.port())
+            .addr(addr)
+            .starttls(StartTls::Ftp)
+            .build_async()
+            .await
+            .unwrap();
+        assert_eq!(scanner.scan().await.error(), None);
+    }
+}
+
+// The longest plaintext reply read while upgrading
+const MAX_REPLY_LEN: usize = 16384;
+const LDAP_STARTTLS_OID: &[u8] = b"1.3.6.1.4.1.1466.20037";
+const POSTGRES_SSL_REQUEST: &[u8] = b"\x00\x00\x00\x08\x04\xd2\x16\x2f";
+
+/// A plaintext protocol that is upgraded to TLS before each probe is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartTls {
+    /// `EHLO` then `STARTTLS`
+    Smtp,
+    /// `a001 STARTTLS`
+    Imap,
+    /// `STLS`
+    Pop3,
+    /// `AUTH TLS`
+    Ftp,
+    /// `<starttls/>` on a client stream
+    Xmpp,
+    /// The StartTLS extended operation
+    Ldap,
+    /// An SSLRequest
+    Postgres,
+}
+
+impl StartTls {
+    pub const ALL: [StartTls; 7] = [
+        StartTls::Smtp,
+        StartTls::Imap,
+        StartTls::Pop3,
+        StartTls::Ftp,
+        StartTls::Xmpp,
+        StartTls::Ldap,
+        StartTls::Postgres,
+    ];
+    /// The port the protocol usually listens on.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            StartTls::Smtp => 25,
+            StartTls::Imap => 143,
+            StartTls::Pop3 => 110,
+            StartTls::Ftp => 21,
+            StartTls::Xmpp => 5222,
+            StartTls::Ldap => 389,
+            StartTls::Postgres => 5432,
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StartTls::Smtp => "smtp",
+            StartTls::Imap => "imap",
+            StartTls::Pop3 => "pop3",
+            StartTls::Ftp => "ftp",
+            StartTls::Xmpp => "xmpp",
+            StartTls::Ldap => "ldap",
+            StartTls::Postgres => "postgres",
+        }
+    }
+    // #What to send and what to wait for, `domain` is the XMPP server name
+    pub(crate) fn steps(&self, domain: &str) -> Vec<Step> {
+        match self {
+            StartTls::Smtp => vec![
+                Step::new(b"", |r| reply_code(r, b"220")),
+                Step::new(b"EHLO jarm\r\n", |r| reply_code(r, b"250")),
+                Step::new(b"STARTTLS\r\n", |r| reply_code(r, b"220")),
+            ],
+            StartTls::Imap => vec![
+                Step::new(b"", |r| {
+                    first_line(r).map(|l| l.starts_with(b"* OK") || l.starts_with(b"* PREAUTH"))
+                }),
+                Step::new(b"a001 STARTTLS\r\n", |r| {
+                    complete_lines(r)
+                        .find(|l| l.starts_with(b"a001 "))
+                        .map(|l| l.starts_with(b"a001 OK"))
+                }),
+            ],
+            StartTls::Pop3 => vec![
+                Step::new(b"", |r| first_line(r).map(|l| l.starts_with(b"+OK"))),
+                Step::new(b"STLS\r\n", |r| {
+                    first_line(r).map(|l| l.starts_with(b"+OK"))
+                }),
+            ],
+            StartTls::Ftp => vec![
+                Step::new(b"", |r| reply_code(r, b"220")),
+                Step::new(b"AUTH TLS\r\n", |r| reply_code(r, b"234")),
+            ],
+            StartTls::Xmpp => {
+                let stream = format!(
+                    "<?xml version='1.0'?><stream:stream xmlns='jabber:client' \
+                     xmlns:stream='http://etherx.jabber.org/streams' to='{}' version='1.0'>",
+                    domain
+                );
+                vec![
+                    Step::new(stream.as_bytes(), |r| {
+                        if contains(r, b"<stream:error") || contains(r, b"</stream:stream>") {
+                            Some(false)
+                        } else if contains(r, b"</stream:features>")
+                            || contains(r, b"<stream:features/>")
+                        {
+                            Some(contains(r, b"<starttls"))
+                        } else {
+                            None
+                        }
+                    }),
+                    Step::new(
+                        b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>",
+                        |r| {
+                            if contains(r, b"<proceed") {
+                                Some(true)
+                            } else if contains(r, b"<failure") || contains(r, b"</stream:stream>") {
+                                Some(false)
+                            } else {
+                                None
+                            }
+                        },
+                    ),
+                ]
+            }
+            StartTls::Ldap => {
+                // LDAPMessage { messageID 1, ExtendedRequest { requestName } }
+                let mut request = vec![0x30, 0x1d, 0x02, 0x01, 0x01, 0x77, 0x18, 0x80, 0x16];
+                request.extend(LDAP_STARTTLS_OID);
+                vec![Step::new(&request, ldap_result)]
+            }
+            StartTls::Postgres => vec![Step::new(POSTGRES_SSL_REQUEST, |r| {
+                r.first().map(|b| *b == b'S')
+            })],
+        }
+    }
+}
+
+impl fmt::Display for StartTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownStartTls(pub String);
+
+impl fmt::Display for UnknownStartTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown starttls protocol {:?}, expected one of smtp, imap, pop3, ftp, xmpp, ldap, postgres",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownStartTls {}
+
+impl FromStr for StartTls {
+    type Err = UnknownStartTls;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        if name == "postgresql" {
+            return Ok(StartTls::Postgres);
+        }
+        StartTls::ALL
+            .into_iter()
+            .find(|p| p.as_str() == name)
+            .ok_or_else(|| UnknownStartTls(s.to_string()))
+    }
+}
+
+/// One exchange of an upgrade: what to send, and whether the reply so far
+/// accepts (`Some(true)`), refuses (`Some(false)`) or is not complete yet.
+pub(crate) struct Step {
+    pub(crate) send: Vec<u8>,
+    reply: fn(&[u8]) -> Option<bool>,
+}
+
+impl Step {
+    fn new(send: &[u8], reply: fn(&[u8]) -> Option<bool>) -> Self {
+        Step {
+            send: send.to_vec(),
+            reply,
+        }
+    }
+    /// `Ok(true)` once the reply accepted the upgrade, `Ok(false)` to read more.
+    pub(crate) fn check(&self, reply: &[u8]) -> Result<bool, JarmError> {
+        match (self.reply)(reply) {
+            Some(true) => Ok(true),
+            Some(false) => Err(refused(reply)),
+            None if reply.len() >= MAX_REPLY_LEN => Err(refused(reply)),
+            None => Ok(false),
+        }
+    }
+}
+
+fn refused(reply: &[u8]) -> JarmError {
+    let line = first_line(reply).unwrap_or(reply);
+    let line = String::from_utf8_lossy(&line[..line.len().min(128)]);
+    JarmError::StartTls(line.trim().to_string())
+}
+
+fn complete_lines(reply: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let end = reply.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+    reply[..end].split_inclusive(|b| *b == b'\n').map(|line| {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        line.strip_suffix(b"\r").unwrap_or(line)
+    })
+}
+
+fn first_line(reply: &[u8]) -> Option<&[u8]> {
+    complete_lines(reply).next()
+}
+
+// #The last line of a multiline `220-...` reply starts with the code and a space
+fn reply_code(reply: &[u8], code: &[u8]) -> Option<bool> {
+    let last = complete_lines(reply).find(|l| l.get(3) != Some(&b'-'))?;
+    Some(last.starts_with(code))
+}
+
+fn contains(reply: &[u8], needle: &[u8]) -> bool {
+    reply.windows(needle.len()).any(|w| w == needle)
+}
+
+// #A BER length, and how many bytes it took
+fn ber_length(data: &[u8]) -> Option<(usize, usize)> {
+    let first = *data.first()?;
+    if first < 0x80 {
+        return Some((first as usize, 1));
+    }
+    let n = (first & 0x7f) as usize;
+    if n == 0 || n > 4 {
+        return Some((usize::MAX, 1));
+    }
+    let length = data
+        .get(1..1 + n)?
+        .iter()
+        .fold(0, |l, b| (l << 8) | *b as usize);
+    Some((length, 1 + n))
+}
+
+// #An ExtendedResponse with resultCode success(0)
+fn ldap_result(reply: &[u8]) -> Option<bool> {
+    if reply.first() != Some(&0x30) {
+        return reply.first().map(|_| false);
+    }
+    let (length, size) = ber_length(&reply[1..])?;
+    let message = reply.get(1 + size..(1 + size).checked_add(length)?)?;
+    // skip the messageID
+    let (id_length, id_size) = match (message.first(), ber_length(message.get(1..)?)) {
+        (Some(0x02), Some(id)) => id,
+        _ => return Some(false),
+    };
+    let op = message.get(1 + id_size + id_length..)?;
+    let (_, op_size) = ber_length(op.get(1..)?)?;
+    Some(op.first() == Some(&0x78) && op.get(1 + op_size..4 + op_size) == Some(&[0x0a, 0x01, 0x00]))
+}
+
+/// Run the upgrade on a blocking stream, reading for at most `timeout`.
+pub(crate) fn upgrade(
+    stream: &mut TcpStream,
+    protocol: StartTls,
+    domain: &str,
+    timeout: Duration,
+) -> Result<(), JarmError> {
+    let deadline = Instant::now() + timeout;
+    let mut data = [0_u8; 1484];
+    for step in protocol.steps(domain) {
+        stream.write_all(&step.send).map_err(JarmError::read)?;
+        let mut reply = Vec::new();
+        while !step.check(&reply)? {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(JarmError::ReadTimeout);
+            }
+            stream
+                .set_read_timeout(Some(remaining))
+                .map_err(JarmError::read)?;
+            match stream.read(&mut data).map_err(JarmError::read)? {
+                0 => return Err(JarmError::ConnectionClosed),
+                n => reply.extend_from_slice(&data[..n]),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+
+// ServerHello selecting TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256 with h2
+const SERVER_HELLO: &str = "1603030064020000600303000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac02f000018ff01000100001000050003026832000b0002010000170000";
+
+pub(crate) fn server_hello() -> Vec<u8> {
+    hex::decode(SERVER_HELLO).unwrap()
+}
+
+// Listen on a free port of 127.0.0.1 and hand each connection, with its index,
+// to `answer` on a thread of its own
+pub(crate) fn listen<F>(answer: F) -> SocketAddr
+where
+    F: Fn(usize, TcpStream) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let answer = Arc::new(answer);
+    std::thread::spawn(move || {
+        for (index, stream) in listener.incoming().enumerate() {
+            let answer = answer.clone();
+            let stream = stream.unwrap();
+            std::thread::spawn(move || answer(index, stream));
+        }
+    });
+    addr
+}
+
+// Answer every client hello with the same server hello
+pub(crate) fn hello_server() -> SocketAddr {
+    listen(|_, mut stream| {
+        let mut hello = [0; 4096];
+        let _ = stream.read(&mut hello);
+        let _ = stream.write_all(&server_hello());
+    })
+}
@@ -0,0 +1,240 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme, StreamOwned};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+
+use crate::builder::Config;
+use crate::connector::Target;
+use crate::{starttls, Certificate, Handshake, HttpResponse, JarmError, MAX_RESPONSE_LEN};
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::listen;
+    use crate::Scanner;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+
+    // A TLS 1.3 server for localhost that answers any request with a fixed response
+    fn server() -> u16 {
+        let fixture: toml::Table =
+            toml::from_str(include_str!("../tests/fixtures/localhost.toml")).unwrap();
+        let der = |key: &str| -> Vec<u8> {
+            let text: String = fixture[key].as_str().unwrap().split_whitespace().collect();
+            hex::decode(text).unwrap()
+        };
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(der("certificate"))],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(der("key"))),
+            )
+            .unwrap();
+        let config = Arc::new(config);
+        listen(move |_, stream| {
+            let connection = ServerConnection::new(config.clone()).unwrap();
+            let mut tls = StreamOwned::new(connection, stream);
+            let mut request = [0; 1024];
+            if tls.read(&mut request).unwrap_or(0) > 0 {
+                let _ = tls.write_all(
+                    b"HTTP/1.1 200 OK\r\nServer: jarm-test\r\nContent-Length: 0\r\n\r\n",
+                );
+                tls.conn.send_close_notify();
+                let _ = tls.flush();
+            }
+        })
+        .port()
+    }
+
+    #[test]
+    fn tls1_3_certificate_and_http() {
+        let port = server();
+        let result = Scanner::builder("localhost", port)
+            .addr(([127, 0, 0, 1], port).into())
+            .retries(0)
+            .full_handshake(true)
+            .http(true)
+            .build()
+            .unwrap()
+            .scan();
+        // the probes never see the certificate of a TLS 1.3 server
+        assert_eq!(result.certificate, None);
+        let handshake = result.handshake.unwrap();
+        assert_eq!(handshake.error, None);
+        assert_eq!(handshake.version.as_deref(), Some("0304"));
+        assert_eq!(handshake.certificates.len(), 1);
+        assert_eq!(handshake.certificates[0].subject, "O=jarm-rs, CN=localhost");
+        let http = handshake.http.unwrap();
+        assert_eq!(http.status, 200);
+        assert_eq!(http.server.as_deref(), Some("jarm-test"));
+        // nothing listens there
+        let closed = Scanner::builder("127.0.0.1", 1)
+            .retries(0)
+            .full_handshake(true)
+            .build()
+            .unwrap()
+            .scan();
+        assert_eq!(
+            closed.handshake.unwrap().error,
+            Some(crate::JarmError::ConnectRefused)
+        );
+    }
+}
+
+// #Accepts any certificate, the handshake is for looking at it, not trusting it
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn client_config(sni: bool, http: bool) -> Result<Arc<ClientConfig>, JarmError> {
+    let provider = Arc::new(ring::default_provider());
+    let mut config = ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(rustls::ALL_VERSIONS)
+        .map_err(|e| JarmError::Tls(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+        .with_no_client_auth();
+    config.enable_sni = sni;
+    if http {
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    }
+    Ok(Arc::new(config))
+}
+
+// #rustls reports its own errors as invalid data
+fn tls_error(e: io::Error) -> JarmError {
+    match e.kind() {
+        io::ErrorKind::InvalidData => JarmError::Tls(e.to_string()),
+        _ => JarmError::read(e),
+    }
+}
+
+/// Connect like the probes do, complete a handshake with rustls and send a
+/// `GET /` when `http` is set.
+pub(crate) fn handshake(
+    target: &Target,
+    host: &str,
+    port: u16,
+    http: bool,
+    config: &Config,
+) -> Handshake {
+    let mut handshake = Handshake::default();
+    if let Err(e) = run(&mut handshake, target, host, port, http, config) {
+        handshake.error = Some(e);
+    }
+    handshake
+}
+
+fn run(
+    handshake: &mut Handshake,
+    target: &Target,
+    host: &str,
+    port: u16,
+    http: bool,
+    config: &Config,
+) -> Result<(), JarmError> {
+    let mut stream = config.connect(target).map_err(JarmError::connect)?;
+    let domain = config.sni.as_deref().unwrap_or(host);
+    if let Some(protocol) = config.starttls {
+        starttls::upgrade(&mut stream, protocol, domain, config.read_timeout)?;
+    }
+    stream
+        .set_read_timeout(Some(config.read_timeout))
+        .map_err(JarmError::read)?;
+    stream
+        .set_write_timeout(Some(config.read_timeout))
+        .map_err(JarmError::read)?;
+    // #Without SNI the name only has to be valid, it is not sent
+    let name = ServerName::try_from(domain.to_string()).unwrap_or(ServerName::IpAddress(
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED).into(),
+    ));
+    let mut connection = ClientConnection::new(client_config(config.sni.is_some(), http)?, name)
+        .map_err(|e| JarmError::Tls(e.to_string()))?;
+    while connection.is_handshaking() {
+        connection.complete_io(&mut stream).map_err(tls_error)?;
+    }
+    handshake.version = connection
+        .protocol_version()
+        .map(|v| hex::encode(u16::from(v).to_be_bytes()));
+    handshake.cipher = connection
+        .negotiated_cipher_suite()
+        .map(|s| hex::encode(u16::from(s.suite()).to_be_bytes()));
+    handshake.alpn = connection
+        .alpn_protocol()
+        .map(|p| String::from_utf8_lossy(p).into_owned());
+    handshake.key_exchange = connection
+        .negotiated_key_exchange_group()
+        .map(|g| format!("{:?}", g.name()));
+    handshake.certificates = connection
+        .peer_certificates()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|c| Certificate::from_der(c))
+        .collect();
+    if !http {
+        return Ok(());
+    }
+    let mut tls = StreamOwned::new(connection, stream);
+    let authority = match (domain.contains(':'), port) {
+        (true, 443) => format!("[{}]", domain),
+        (true, _) => format!("[{}]:{}", domain, port),
+        (false, 443) => domain.to_string(),
+        (false, _) => format!("{}:{}", domain, port),
+    };
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: {}\r\nUser-Agent: jarm-rs\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        authority
+    );
+    tls.write_all(request.as_bytes()).map_err(tls_error)?;
+    // #Read the head of the response, the body does not matter
+    let mut response = Vec::new();
+    let mut data = [0_u8; 4096];
+    while !response.windows(4).any(|w| w == b"\r\n\r\n") && response.len() < MAX_RESPONSE_LEN {
+        match tls.read(&mut data) {
+            Ok(0) => break,
+            Ok(n) => response.extend_from_slice(&data[..n]),
+            Err(e) if response.is_empty() => return Err(tls_error(e)),
+            Err(_) => break,
+        }
+    }
+    handshake.http = HttpResponse::parse(&response);
+    Ok(())
+}
@@ -0,0 +1,119 @@
+#!/usr/bin/env python3
+"""Regenerate client_hellos.toml with the ClientHello code of the reference
+implementation, jarm.py of https://github.com/salesforce/jarm
+
+    git clone https://github.com/salesforce/jarm /tmp/jarm
+    python3 jarm/tests/golden/client_hellos.py /tmp/jarm/jarm.py > jarm/tests/golden/client_hellos.toml
+
+jarm.py parses its command line and starts scanning when it is run, so only
+its functions, its literal constants and the ten probe lists are taken from
+it, and the upstream commit they came from is written to the output. Its two
+sources of randomness are patched to make the output reproducible:
+os.urandom(n) and random.choice() both draw from a counter that starts at 0
+for every probe, the same stream as `rand::rngs::mock::StepRng::new(0, 1)`.
+The counter stays small, so random.choice() picks the first GREASE value, as
+`gen_range` does on the Rust side.
+"""
+import ast
+import itertools
+import os
+import random
+import struct
+import subprocess
+import sys
+
+HOST = "example.com"
+PORT = 443
+PROBES = [
+    "tls1_2_forward",
+    "tls1_2_reverse",
+    "tls1_2_top_half",
+    "tls1_2_bottom_half",
+    "tls1_2_middle_out",
+    "tls1_1_middle_out",
+    "tls1_3_forward",
+    "tls1_3_reverse",
+    "tls1_3_invalid",
+    "tls1_3_middle_out",
+]
+
+counter = itertools.count()
+
+
+def urandom(n):
+    return bytes(next(counter) & 0xFF for _ in range(n))
+
+
+def choice(values):
+    next(counter)
+    return values[0]
+
+
+def is_literal(node):
+    try:
+        ast.literal_eval(node)
+        return True
+    except ValueError:
+        return False
+
+
+def load(path):
+    """The functions of jarm.py and the options of each probe, by name."""
+    with open(path) as f:
+        tree = ast.parse(f.read(), path)
+    definitions = [
+        node
+        for node in tree.body
+        if isinstance(node, ast.FunctionDef)
+        or (isinstance(node, ast.Assign) and is_literal(node.value))
+    ]
+    namespace = {"os": os, "random": random, "struct": struct}
+    exec(compile(ast.Module(body=definitions, type_ignores=[]), path, "exec"), namespace)
+    # [destination_host, destination_port, version, ..., extension_order]
+    options = {}
+    for node in ast.walk(tree):
+        if isinstance(node, ast.Assign) and isinstance(node.value, ast.List):
+            for target in node.targets:
+                if isinstance(target, ast.Name) and target.id in PROBES:
+                    options[target.id] = [ast.literal_eval(e) for e in node.value.elts[2:]]
+    missing = [name for name in PROBES if name not in options]
+    if missing:
+        sys.exit("%s does not define the probes %s" % (path, ", ".join(missing)))
+    return namespace, options
+
+
+def upstream_commit(path):
+    directory = os.path.dirname(os.path.abspath(path))
+    try:
+        return subprocess.run(
+            ["git", "-C", directory, "rev-parse", "HEAD"],
+            capture_output=True,
+            text=True,
+            check=True,
+        ).stdout.strip()
+    except (OSError, subprocess.CalledProcessError):
+        sys.exit("%s is not in a git checkout of salesforce/jarm" % path)
+
+
+def main():
+    if len(sys.argv) != 2:
+        sys.exit("usage: client_hellos.py path/to/jarm.py")
+    path = sys.argv[1]
+    commit = upstream_commit(path)
+    namespace, options = load(path)
+    os.urandom = urandom
+    random.choice = choice
+    print("# Generated by client_hellos.py, the ClientHello of each probe to %s" % HOST)
+    print("# with jarm.py of salesforce/jarm at %s" % commit)
+    print('host = "%s"' % HOST)
+    print()
+    print("[probes]")
+    global counter
+    for name in PROBES:
+        counter = itertools.count()
+        packet = namespace["packet_building"]([HOST, PORT] + options[name])
+        print('%s = "%s"' % (name, packet.hex()))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,203 @@
+#!/usr/bin/env python3
+"""Regenerate server_hellos.toml, known ServerHellos and their JA3S, JA4S and JA4X.
+
+JA3S is computed as https://github.com/salesforce/ja3 does for servers: the
+decimal legacy version, selected cipher and extension types in the order
+the server sent them, joined as `version,cipher,ext-ext` and hashed with MD5.
+
+JA4S and JA4X follow https://github.com/FoxIO-LLC/ja4. JA4S is
+`t<version><extension count><alpn>_<cipher>_<hash>` where the hash is the
+first 12 hex characters of the SHA-256 of the extension types in hex, in
+the order the server sent them. JA4X hashes the OIDs of the leaf
+certificate's issuer attributes, subject attributes and extensions.
+
+    python3 jarm/tests/golden/server_hellos.py > jarm/tests/golden/server_hellos.toml
+"""
+import hashlib
+import struct
+
+# A leaf for example.com, www.example.com and 127.0.0.1 with an EC P-256 key,
+# issued by a test CA, and the CA itself
+LEAF = (
+    "308201ab30820152a003020102020102300a06082a8648ce3d0403023039310b30090603550406130255533110300e06"
+    "0355040a0c076a61726d2d72733118301606035504030c0f6a61726d2d72732074657374204341301e170d3234303130"
+    "313030303030305a170d3334303130313030303030305a3035310b30090603550406130255533110300e060355040a0c"
+    "074578616d706c653114301206035504030c0b6578616d706c652e636f6d3059301306072a8648ce3d020106082a8648"
+    "ce3d0301070342000455d2d18c0e620995acdc3b1e5fe3724de9160e027b72b5c8d9c2cac46bcb945a1de60887741414"
+    "7c9a5063c7db93e670db15b74f1fcb84e0f0b094ca9d1e833ba34f304d300c0603551d130101ff04023000300e060355"
+    "1d0f0101ff040403020780302d0603551d1104263024820b6578616d706c652e636f6d820f7777772e6578616d706c65"
+    "2e636f6d87047f000001300a06082a8648ce3d040302034700304402207502462941f2acb83f6f3bae9bd83cf5fe08e5"
+    "a2a00d4899a139dd49856d327102204f68fdea137ef6b625e929fe0599af9acbe34580da9289b8a2410705fe028478"
+)
+CA = (
+    "308201743082011aa003020102020101300a06082a8648ce3d0403023039310b30090603550406130255533110300e06"
+    "0355040a0c076a61726d2d72733118301606035504030c0f6a61726d2d72732074657374204341301e170d3234303130"
+    "313030303030305a170d3334303130313030303030305a3039310b30090603550406130255533110300e060355040a0c"
+    "076a61726d2d72733118301606035504030c0f6a61726d2d727320746573742043413059301306072a8648ce3d020106"
+    "082a8648ce3d03010703420004f149b78cca6293f54dbdd2680915f14c1f9cdd5cd40dc6d02ff76bbacd60c9a72f7eb9"
+    "066aa56619f8a8bedb0e08293eb3300608cc444b531d02330666c6c1e3a3133011300f0603551d130101ff0405300301"
+    "01ff300a06082a8648ce3d0403020348003045022100a3f643c1b7739c9ed0e44ffa41f796301767adac530bae8e222d"
+    "953dd24b81b802205f18b4715dfe3db57e4445fdbf47c2f6259b7fece23f15c4bb3f0bda29f3d0de"
+)
+
+
+def certificate_message(chain):
+    certs = b"".join(len(c).to_bytes(3, "big") + c for c in chain)
+    body = len(certs).to_bytes(3, "big") + certs
+    return b"\x0b" + len(body).to_bytes(3, "big") + body
+
+
+def with_certificates(record, chain):
+    """Append a Certificate and a ServerHelloDone to the server hello record."""
+    handshake = record[5:] + certificate_message(chain) + b"\x0e\x00\x00\x00"
+    return record[:3] + len(handshake).to_bytes(2, "big") + handshake
+
+
+HELLOS = [
+    ("tls1_2_h2",
+     "Selects ECDHE-RSA-AES128-GCM-SHA256 with h2",
+     "1603030064020000600303000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac02f000018ff01000100001000050003026832000b0002010000170000"),
+    ("tls1_2_openssl",
+     "nginx on OpenSSL 1.1.1 with session tickets, the tls1_2_forward reply of the openssl fixture",
+     "160303006e0200006a030319c59b0a4890383eea59539173bfca5dc78e5e99037f4ad65c93d5b777b8720e2053453a19cbc7f60351e13709237b6025007b6bf5a6974aabaa03d5e19ea1ada3c030000022ff0100010000000000000b0004030001020023000000100005000302683200170000"),
+    ("tls1_3",
+     "TLS 1.3 with TLS_AES_256_GCM_SHA384, the tls1_3_forward reply of the openssl fixture",
+     "160303007a020000760303eb588580d5795de5537d25b4b57f30f27461c269e1731b362b1d29d43153fc472006acdefc2c380277c70dd64a83e9e59c9612ef0ce8f6fae65d65cfa2dea11c09130200002e002b0002030400330024001d00202c70e12b7a0646f92279f427c7b38e7334d8e5389cff167a1dc30e73f826b683"),
+    ("tls1_1_no_extensions",
+     "TLS 1.1 with AES128-SHA and no extensions, from the legacy fixture",
+     "160303004a020000460302ed8e6b77b811e0917341671c7ee0ba992d2a19f8c3c0f9193e01c3b872399cb520c99e7b4fd272488e24ba819041926c6203395e14f513ef45a4afbfaf74c746d9002f00"),
+    ("tls1_2_certificate",
+     "The tls1_2_h2 hello followed by the leaf and CA certificates and a ServerHelloDone",
+     with_certificates(
+         bytes.fromhex("1603030064020000600303000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac02f000018ff01000100001000050003026832000b0002010000170000"),
+         [bytes.fromhex(LEAF), bytes.fromhex(CA)],
+     ).hex()),
+]
+
+
+def server_hello(record):
+    """The version, cipher, extensions and following handshake messages."""
+    hello = record[5:]
+    assert hello[0] == 2
+    length = int.from_bytes(hello[1:4], "big")
+    body = hello[4:4 + length]
+    version, = struct.unpack(">H", body[:2])
+    pos = 2 + 32
+    pos += 1 + body[pos]
+    cipher, = struct.unpack(">H", body[pos:pos + 2])
+    pos += 3
+    extensions = []
+    if pos < len(body):
+        end = pos + 2 + int.from_bytes(body[pos:pos + 2], "big")
+        pos += 2
+        while pos < end:
+            kind, size = struct.unpack(">HH", body[pos:pos + 4])
+            extensions.append((kind, body[pos + 4:pos + 4 + size]))
+            pos += 4 + size
+    return version, cipher, extensions, hello[4 + length:]
+
+
+def ja3s(record):
+    version, cipher, extensions, _ = server_hello(record)
+    return "%d,%d,%s" % (version, cipher, "-".join(str(kind) for kind, _ in extensions))
+
+
+def sha12(items):
+    if not items:
+        return "000000000000"
+    return hashlib.sha256(",".join(items).encode()).hexdigest()[:12]
+
+
+VERSIONS = {0x0304: "13", 0x0303: "12", 0x0302: "11", 0x0301: "10", 0x0300: "s3"}
+
+
+def ja4s(record, raw=False):
+    version, cipher, extensions, _ = server_hello(record)
+    alpn = "00"
+    for kind, value in extensions:
+        if kind == 0x002b:
+            version, = struct.unpack(">H", value)
+        if kind == 0x0010:
+            protocol = value[3:3 + value[2]]
+            if chr(protocol[0]).isalnum() and chr(protocol[-1]).isalnum():
+                alpn = chr(protocol[0]) + chr(protocol[-1])
+            else:
+                alpn = protocol.hex()[0] + protocol.hex()[-1]
+    types = ["%04x" % kind for kind, _ in extensions]
+    a = "t%s%02d%s" % (VERSIONS.get(version, "00"), min(len(extensions), 99), alpn)
+    return "%s_%04x_%s" % (a, cipher, ",".join(types) if raw else sha12(types))
+
+
+def der(data, pos):
+    """The tag, content and end of the DER element at pos."""
+    tag = data[pos]
+    length = data[pos + 1]
+    pos += 2
+    if length & 0x80:
+        size = length & 0x7f
+        length = int.from_bytes(data[pos:pos + size], "big")
+        pos += size
+    return tag, data[pos:pos + length], pos + length
+
+
+def children(data):
+    pos = 0
+    while pos < len(data):
+        tag, content, pos = der(data, pos)
+        yield tag, content
+
+
+def name_oids(name):
+    # Name is a sequence of sets of (type, value) sequences
+    return [
+        next(children(attribute))[1].hex()
+        for _, rdn in children(name)
+        for _, attribute in children(rdn)
+    ]
+
+
+def ja4x(cert, raw=False):
+    _, certificate, _ = der(cert, 0)
+    _, tbs = next(children(certificate))
+    fields = list(children(tbs))
+    if fields[0][0] == 0xa0:
+        fields = fields[1:]
+    issuer, subject = fields[2][1], fields[4][1]
+    extensions = []
+    for tag, content in fields[6:]:
+        if tag == 0xa3:
+            _, sequence, _ = der(content, 0)
+            extensions = [next(children(e))[1].hex() for _, e in children(sequence)]
+    parts = [name_oids(issuer), name_oids(subject), extensions]
+    return "_".join(",".join(p) if raw else sha12(p) for p in parts)
+
+
+def leaf(record):
+    rest = server_hello(record)[3]
+    while rest:
+        kind, length = rest[0], int.from_bytes(rest[1:4], "big")
+        if kind == 11:
+            body = rest[4:4 + length]
+            return body[6:6 + int.from_bytes(body[3:6], "big")]
+        rest = rest[4 + length:]
+    return None
+
+
+if __name__ == "__main__":
+    print("# Generated by server_hellos.py")
+    for name, description, data in HELLOS:
+        record = bytes.fromhex(data)
+        string = ja3s(record)
+        print()
+        print("[[hello]]")
+        print('name = "%s"' % name)
+        print("# %s" % description)
+        print('data = "%s"' % data)
+        print('ja3s = "%s"' % string)
+        print('ja3s_hash = "%s"' % hashlib.md5(string.encode()).hexdigest())
+        print('ja4s = "%s"' % ja4s(record))
+        print('ja4s_raw = "%s"' % ja4s(record, raw=True))
+        cert = leaf(record)
+        if cert:
+            print('ja4x = "%s"' % ja4x(cert))
+            print('ja4x_raw = "%s"' % ja4x(cert, raw=True))
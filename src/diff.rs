@@ -0,0 +1,261 @@
+use crate::output::Record;
+use jarm::{JarmFingerprint, ProbeResult};
+use serde::Deserialize;
+use std::fmt::Write;
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{render, Side};
+    use jarm::{JarmFingerprint, ProbeResult};
+    use std::str::FromStr;
+
+    fn probe(cipher: &str, version: &str, extensions: &[&str]) -> ProbeResult {
+        ProbeResult {
+            name: "tls1_2_forward".to_string(),
+            cipher: Some(cipher.to_string()),
+            version: Some(version.to_string()),
+            alpn: None,
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            ja3s: None,
+            ja3s_hash: None,
+            ja4s: None,
+            ja4x: None,
+            bytes_read: 100,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn saved_results() {
+        let record = r#"{"host": "a.example", "port": 443, "fingerprint": null, "probes": []}"#;
+        for text in [format!("[{}]", record), format!("{}\n{}\n", record, record)] {
+            let side = Side::parse(&text, "saved.json").unwrap();
+            assert_eq!(side.label, "a.example:443");
+            assert!(side.fingerprint.is_none());
+        }
+        // a library ScanResult has no host
+        let side = Side::parse(r#"{"addr": null, "fingerprint": "00000000000000000000000000000000000000000000000000000000000000", "probes": []}"#, "scan.json").unwrap();
+        assert_eq!(side.label, "scan.json");
+        assert!(side.fingerprint.unwrap().is_zero());
+        assert!(Side::parse("[]", "empty.json").is_err());
+        assert!(Side::parse("fingerprint", "bad.json").is_err());
+    }
+
+    #[test]
+    fn highlights_changes() {
+        let left = Side {
+            label: "old".to_string(),
+            fingerprint: Some(
+                JarmFingerprint::from_str(
+                    "2ad2ad0002ad2ad00042d42d00000069d641f34fe76acdc05c40262f8815e5",
+                )
+                .unwrap(),
+            ),
+            probes: vec![
+                probe("c02f", "0303", &["ff01", "0010"]),
+                ProbeResult::from_response("tls1_2_reverse", b"\x15\x03\x03\x00\x02\x02\x28"),
+            ],
+        };
+        let mut right = left.clone();
+        right.label = "new".to_string();
+        right.probes[0] = probe("c030", "0303", &["ff01"]);
+        let (text, changed) = render(&left, &right, false);
+        assert!(changed);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].contains("old") && lines[0].contains("new"));
+        let cipher = lines.iter().find(|l| l.contains("cipher")).unwrap();
+        assert!(cipher.starts_with('*'));
+        assert!(cipher.contains("TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"));
+        assert!(cipher.contains("TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384"));
+        let version = lines.iter().find(|l| l.contains("TLS 1.2")).unwrap();
+        assert!(version.starts_with(' '));
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with('*') && l.contains("ff01-0010")));
+        assert!(lines
+            .iter()
+            .any(|l| l.contains("tls1_2_reverse") && l.contains("alert")));
+        // a custom probe set with two probes
+        let custom = |fingerprint: &str| Side {
+            fingerprint: Some(JarmFingerprint::from_str(fingerprint).unwrap()),
+            ..left.clone()
+        };
+        let (text, _) = render(
+            &custom("2ad2ad69d641f34fe76acdc05c40262f8815e5"),
+            &custom("2ad00069d641f34fe76acdc05c40262f8815e5"),
+            false,
+        );
+        assert!(text.contains("1 of 2 probes differ"), "{}", text);
+        let (text, changed) = render(&left, &left, true);
+        assert!(!changed);
+        assert!(!text.contains('\x1b'));
+        assert!(text.ends_with("identical\n"));
+    }
+}
+
+/// One side of a diff, a fresh scan or a saved result.
+#[derive(Debug, Clone)]
+pub struct Side {
+    /// `host:port` or the file name
+    pub label: String,
+    pub fingerprint: Option<JarmFingerprint>,
+    pub probes: Vec<ProbeResult>,
+}
+
+// A record from `--format json` or `ndjson`, or a library `ScanResult`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Saved {
+    List(Vec<SavedRecord>),
+    Record(SavedRecord),
+}
+
+#[derive(Deserialize)]
+struct SavedRecord {
+    host: Option<String>,
+    port: Option<u16>,
+    fingerprint: Option<JarmFingerprint>,
+    #[serde(default)]
+    probes: Vec<ProbeResult>,
+}
+
+impl Side {
+    pub fn from_record(record: Record) -> Self {
+        Side {
+            label: record.target(),
+            fingerprint: record.fingerprint,
+            probes: record.probes,
+        }
+    }
+    /// Read the first result saved in a json or ndjson file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Side::parse(&text, path)
+    }
+    fn parse(text: &str, path: &str) -> Result<Self, String> {
+        let saved = serde_json::Deserializer::from_str(text)
+            .into_iter::<Saved>()
+            .next()
+            .ok_or_else(|| format!("{}: no saved result", path))?
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let record = match saved {
+            Saved::Record(record) => record,
+            Saved::List(records) => records
+                .into_iter()
+                .next()
+                .ok_or_else(|| format!("{}: no saved result", path))?,
+        };
+        let label = match (record.host, record.port) {
+            (Some(host), Some(port)) if host.contains(':') => format!("[{}]:{}", host, port),
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            _ => path.to_string(),
+        };
+        Ok(Side {
+            label,
+            fingerprint: record.fingerprint,
+            probes: record.probes,
+        })
+    }
+}
+
+// #The cipher, version, alpn and extension cells of one probe, or its error
+fn cells(probe: Option<&ProbeResult>) -> [String; 4] {
+    let mut cells = ["-", "-", "-", "-"].map(String::from);
+    let probe = match probe {
+        Some(probe) => probe,
+        None => return cells,
+    };
+    if let Some(error) = &probe.error {
+        cells[0] = error.kind().to_string();
+        return cells;
+    }
+    if let Some(cipher) = probe
+        .cipher_name()
+        .map(String::from)
+        .or(probe.cipher.clone())
+    {
+        cells[0] = cipher;
+    }
+    if let Some(version) = probe
+        .version_name()
+        .map(String::from)
+        .or(probe.version.clone())
+    {
+        cells[1] = version;
+    }
+    if let Some(alpn) = &probe.alpn {
+        cells[2] = alpn.clone();
+    }
+    if !probe.extensions.is_empty() {
+        cells[3] = probe.extensions.join("-");
+    }
+    cells
+}
+
+/// Both sides probe by probe, rows that differ start with `*` and are
+/// yellow with `color`. Also returns whether anything differs.
+pub fn render(left: &Side, right: &Side, color: bool) -> (String, bool) {
+    const FIELDS: [&str; 4] = ["cipher", "version", "alpn", "extensions"];
+    // marker, probe name, field, left, right
+    let mut rows = vec![(
+        false,
+        "probe".to_string(),
+        "",
+        left.label.clone(),
+        right.label.clone(),
+    )];
+    let mut changed = false;
+    for index in 0..left.probes.len().max(right.probes.len()) {
+        let (a, b) = (left.probes.get(index), right.probes.get(index));
+        let name = a.or(b).map(|p| p.name.clone()).unwrap_or_default();
+        for (row, ((field, a), b)) in FIELDS.iter().zip(cells(a)).zip(cells(b)).enumerate() {
+            let differs = a != b;
+            changed |= differs;
+            let name = if row == 0 {
+                name.clone()
+            } else {
+                String::new()
+            };
+            rows.push((differs, name, field, a, b));
+        }
+    }
+    let name_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(0);
+    let left_width = rows.iter().map(|r| r.3.len()).max().unwrap_or(0);
+    let mut text = String::new();
+    for (differs, name, field, a, b) in rows {
+        let marker = if differs { '*' } else { ' ' };
+        let line = format!(
+            "{} {:name_width$}  {:10}  {:left_width$}  {}",
+            marker, name, field, a, b
+        );
+        let line = line.trim_end();
+        let _ = match differs && color {
+            true => writeln!(text, "\x1b[33m{}\x1b[0m", line),
+            false => writeln!(text, "{}", line),
+        };
+    }
+    let _ = match (&left.fingerprint, &right.fingerprint) {
+        (Some(a), Some(b)) => {
+            let similarity = a.compare(b);
+            changed |= !similarity.is_identical();
+            let _ = writeln!(text, "\n  {}\n  {}", a, b);
+            match similarity.is_identical() {
+                true => writeln!(text, "  identical"),
+                false => writeln!(
+                    text,
+                    "  similarity {:.2}, {} of {} probes differ, extension hash {}",
+                    similarity.score,
+                    similarity.differing_probes.len(),
+                    a.probes().count().max(b.probes().count()),
+                    if similarity.extension_hash_match {
+                        "matches"
+                    } else {
+                        "differs"
+                    }
+                ),
+            }
+        }
+        _ => writeln!(text, "\n  no fingerprint to compare"),
+    };
+    (text, changed)
+}
@@ -0,0 +1,279 @@
+use jarm::StartTls;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+#[cfg(test)]
+mod tests {
+    use crate::input::{parse_line, parse_ports, parse_rate, ScanTarget};
+    use jarm::StartTls;
+
+    fn hosts(line: &str) -> Vec<String> {
+        parse_line(line)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn host_and_port() {
+        assert_eq!(hosts("example.com"), ["example.com"]);
+        assert_eq!(hosts("example.com:8443"), ["example.com:8443"]);
+        assert_eq!(
+            hosts("my-host.example.com  # a comment"),
+            ["my-host.example.com"]
+        );
+        assert_eq!(hosts("::1"), ["[::1]"]);
+        assert_eq!(hosts("[2001:db8::1]:8443"), ["[2001:db8::1]:8443"]);
+        assert_eq!(hosts("  # only a comment"), Vec::<String>::new());
+        assert_eq!(hosts(""), Vec::<String>::new());
+        assert_eq!(
+            parse_line("smtp://mail.example.com").unwrap(),
+            [ScanTarget {
+                host: "mail.example.com".to_string(),
+                port: None,
+                starttls: Some(StartTls::Smtp),
+            }]
+        );
+        assert!(parse_line("example.com:https").is_err());
+        assert!(parse_line("[::1").is_err());
+        assert!(parse_line("gopher://example.com").is_err());
+    }
+
+    #[test]
+    fn cidr_and_ranges() {
+        assert_eq!(
+            hosts("10.0.0.0/30"),
+            ["10.0.0.0", "10.0.0.1", "10.0.0.2", "10.0.0.3"]
+        );
+        assert_eq!(hosts("10.0.0.7/32:8443"), ["10.0.0.7:8443"]);
+        assert_eq!(
+            hosts("10.0.0.255-10.0.1.1"),
+            ["10.0.0.255", "10.0.1.0", "10.0.1.1"]
+        );
+        assert_eq!(
+            hosts("[2001:db8::/127]:443"),
+            ["[2001:db8::]:443", "[2001:db8::1]:443"]
+        );
+        assert_eq!(hosts("2001:db8::1-2001:db8::2").len(), 2);
+        assert_eq!(hosts("10.0.0.0/16").len(), 65536);
+        assert!(parse_line("10.0.0.0/33").is_err());
+        assert!(parse_line("10.0.0.5-10.0.0.1").is_err());
+        assert!(parse_line("10.0.0.1-2001:db8::1").is_err());
+        assert!(parse_line("10.0.0.0/4").is_err());
+    }
+
+    #[test]
+    fn ports() {
+        assert_eq!(parse_ports("443").unwrap(), [443]);
+        assert_eq!(
+            parse_ports("443, 8443,9000-9002,443").unwrap(),
+            [443, 8443, 9000, 9001, 9002]
+        );
+        assert!(parse_ports("").is_err());
+        assert!(parse_ports("0").is_err());
+        assert!(parse_ports("9010-9000").is_err());
+        assert!(parse_ports("65536").is_err());
+    }
+
+    #[test]
+    fn rate() {
+        assert_eq!(parse_rate("100/s"), Ok(100));
+        assert_eq!(parse_rate("25"), Ok(25));
+        assert!(parse_rate("0/s").is_err());
+        assert!(parse_rate("10/m").is_err());
+    }
+}
+
+// Refuse to expand a CIDR block or range into more addresses than this
+const MAX_EXPANSION: u128 = 1 << 24;
+
+/// A host to scan, with the port and STARTTLS protocol if the line named them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanTarget {
+    pub host: String,
+    pub port: Option<u16>,
+    pub starttls: Option<StartTls>,
+}
+
+impl std::fmt::Display for ScanTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        match self.port {
+            Some(port) => write!(f, "{}:{}", host, port),
+            None => f.write_str(&host),
+        }
+    }
+}
+
+/// Parse one line of input: a host, IP, CIDR block or IP range, with an
+/// optional `scheme://` for STARTTLS and an optional `:port`.
+///
+/// Comments start with `#`, blank lines give no targets.
+pub fn parse_line(line: &str) -> Result<Vec<ScanTarget>, String> {
+    let line = line.split('#').next().unwrap_or_default().trim();
+    if line.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (starttls, rest) = match line.split_once("://") {
+        Some((scheme, rest)) => (
+            Some(StartTls::from_str(scheme).map_err(|e| e.to_string())?),
+            rest,
+        ),
+        None => (None, line),
+    };
+    let (host, port) = split_port(rest)?;
+    let target = |host: String| ScanTarget {
+        host,
+        port,
+        starttls,
+    };
+    if let Some((network, prefix)) = host.split_once('/') {
+        let network = IpAddr::from_str(network).map_err(|_| format!("invalid network {}", host))?;
+        let prefix = u8::from_str(prefix).map_err(|_| format!("invalid prefix {}", host))?;
+        let (first, last) =
+            cidr(network, prefix).ok_or_else(|| format!("invalid prefix {}", host))?;
+        return Ok(expand(first, last)?.map(target).collect());
+    }
+    if let Some((first, last)) = host.split_once('-') {
+        if let (Ok(first), Ok(last)) = (IpAddr::from_str(first), IpAddr::from_str(last)) {
+            if first.is_ipv4() != last.is_ipv4() {
+                return Err(format!("range {} mixes IPv4 and IPv6", host));
+            }
+            return Ok(expand(first, last)?.map(target).collect());
+        }
+    }
+    Ok(vec![target(host.to_string())])
+}
+
+/// Parse a port list like `443,8443,9000-9010`, keeping the first of any duplicates.
+pub fn parse_ports(input: &str) -> Result<Vec<u16>, String> {
+    let port = |port: &str| match u16::from_str(port.trim()) {
+        Ok(0) | Err(_) => Err(format!("invalid port {:?}", port.trim())),
+        Ok(port) => Ok(port),
+    };
+    let mut ports = Vec::new();
+    let mut seen = HashSet::new();
+    for item in input.split(',') {
+        let (first, last) = match item.split_once('-') {
+            Some((first, last)) => (port(first)?, port(last)?),
+            None => (port(item)?, port(item)?),
+        };
+        if first > last {
+            return Err(format!("port range {} is backwards", item.trim()));
+        }
+        for port in first..=last {
+            if seen.insert(port) {
+                ports.push(port);
+            }
+        }
+    }
+    Ok(ports)
+}
+
+/// Parse a rate like `100/s` or `100` into attempts per second.
+pub fn parse_rate(input: &str) -> Result<u32, String> {
+    let rate = input.strip_suffix("/s").unwrap_or(input);
+    match u32::from_str(rate) {
+        Ok(0) | Err(_) => Err(format!("invalid rate {:?}, expected e.g. 100/s", input)),
+        Ok(rate) => Ok(rate),
+    }
+}
+
+/// Read targets from every line of `reader`, reporting bad lines on stderr.
+pub fn read_targets(reader: impl BufRead, source: &str) -> Vec<ScanTarget> {
+    let mut targets = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("{}: {}", source, e);
+                break;
+            }
+        };
+        match parse_line(&line) {
+            Ok(parsed) => targets.extend(parsed),
+            Err(e) => eprintln!("{}:{}: {}", source, number + 1, e),
+        }
+    }
+    targets
+}
+
+// #Split off the port: `host`, `host:port`, `v6`, `[v6]` or `[v6]:port`
+fn split_port(input: &str) -> Result<(&str, Option<u16>), String> {
+    let parse_port = |port: &str| {
+        u16::from_str(port)
+            .map(Some)
+            .map_err(|_| format!("invalid port {:?} in {}", port, input))
+    };
+    if let Some(rest) = input.strip_prefix('[') {
+        let (host, after) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("missing ] in {}", input))?;
+        return match after {
+            "" => Ok((host, None)),
+            _ => match after.strip_prefix(':') {
+                Some(port) => Ok((host, parse_port(port)?)),
+                None => Err(format!("unexpected {:?} after ] in {}", after, input)),
+            },
+        };
+    }
+    match input.rsplit_once(':') {
+        // more than one colon is a bare IPv6 address, possibly a CIDR block or range
+        Some((host, _)) if host.contains(':') => Ok((input, None)),
+        Some((host, port)) => Ok((host, parse_port(port)?)),
+        None => Ok((input, None)),
+    }
+}
+
+// #The first and last address of a CIDR block
+fn cidr(network: IpAddr, prefix: u8) -> Option<(IpAddr, IpAddr)> {
+    let bits: u32 = if network.is_ipv4() { 32 } else { 128 };
+    if prefix as u32 > bits {
+        return None;
+    }
+    let host_mask = u128::MAX
+        .checked_shr(128 - bits + prefix as u32)
+        .unwrap_or(0);
+    let first = to_u128(network) & !host_mask;
+    Some((
+        from_u128(first, network.is_ipv4()),
+        from_u128(first | host_mask, network.is_ipv4()),
+    ))
+}
+
+fn expand(first: IpAddr, last: IpAddr) -> Result<impl Iterator<Item = String>, String> {
+    let (start, end) = (to_u128(first), to_u128(last));
+    if start > end {
+        return Err(format!("range {}-{} is backwards", first, last));
+    }
+    if end - start >= MAX_EXPANSION {
+        return Err(format!(
+            "{}-{} has more than {} addresses",
+            first, last, MAX_EXPANSION
+        ));
+    }
+    let v4 = first.is_ipv4();
+    Ok((start..=end).map(move |ip| from_u128(ip, v4).to_string()))
+}
+
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u32::from(ip) as u128,
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+fn from_u128(ip: u128, v4: bool) -> IpAddr {
+    if v4 {
+        IpAddr::V4(Ipv4Addr::from(ip as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(ip))
+    }
+}
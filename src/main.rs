@@ -1,13 +1,83 @@
-use std::str::FromStr;
-use jarm::Scanner;
 use argh::FromArgs;
+use jarm::{Capture, FingerprintDb, ProbeSet, RateLimiter, Scanner, StartTls};
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+mod diff;
+mod input;
+mod output;
+
+use diff::Side;
+use input::{parse_line, parse_ports, parse_rate, read_targets, ScanTarget};
+use output::{Format, Output, Record};
 
 #[derive(Debug, Clone, FromArgs, Default)]
 #[argh(description = "TLS Fingerprinting tool")]
 pub struct ConfigArgs {
-    /// specify an IP or domain to scan
+    /// specify an IP, domain, CIDR block or IP range to scan, a scheme like smtp:// upgrades it with STARTTLS
     #[argh(option, short = 't')]
-    pub target: String,
+    pub target: Vec<String>,
+    /// read targets from a file, one per line, or from stdin with -
+    #[argh(option, short = 'i')]
+    pub input: Option<String>,
+    /// ports to scan on targets without one, like 443,8443,9000-9010, 443 by default
+    #[argh(option, short = 'p')]
+    pub ports: Option<String>,
+    /// upgrade with STARTTLS first: smtp, imap, pop3, ftp, xmpp, ldap or postgres
+    #[argh(option)]
+    pub starttls: Option<StartTls>,
+    /// how many host:port pairs to scan at the same time, 1 by default
+    #[argh(option, default = "1")]
+    pub concurrency: usize,
+    /// at most this many connection attempts per second across all scans, like 100/s
+    #[argh(option)]
+    pub rate: Option<String>,
+    /// milliseconds to wait between the probes sent to one host:port
+    #[argh(option, default = "0")]
+    pub delay: u64,
+    /// print results as text, json, ndjson or csv, text by default
+    #[argh(option, default = "Format::Text")]
+    pub format: Format,
+    /// label fingerprints from this .toml, .json or .csv database as well as the bundled one
+    #[argh(option)]
+    pub db: Vec<String>,
+    /// fingerprint the JARM scans in a pcap or pcapng capture instead of scanning
+    #[argh(option)]
+    pub pcap: Option<String>,
+    /// send the probes of this .toml or .json probe set instead of the standard ten
+    #[argh(option)]
+    pub probes: Option<String>,
+    /// read the handshake of TLS 1.2 probes up to the certificate and print the leaf, its SHA-256 and subject in text output
+    #[argh(switch)]
+    pub certs: bool,
+    /// after the probes, complete a handshake with rustls for the certificate chain, and the Server header on HTTP ports
+    #[argh(switch)]
+    pub handshake: bool,
+    #[argh(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    Diff(DiffArgs),
+}
+
+#[derive(Debug, Clone, FromArgs)]
+#[argh(
+    subcommand,
+    name = "diff",
+    description = "compare two targets or two saved json results probe by probe"
+)]
+pub struct DiffArgs {
+    /// a target like host:port, or a file saved with --format json or ndjson
+    #[argh(positional)]
+    pub left: String,
+    /// the target or saved file to compare with
+    #[argh(positional)]
+    pub right: String,
 }
 
 impl ConfigArgs {
@@ -15,29 +85,215 @@ impl ConfigArgs {
         let default: ConfigArgs = argh::from_env();
         default
     }
+    // #Targets from -t, then -i, or stdin when neither was given and it is not a terminal
+    fn targets(&self) -> Vec<ScanTarget> {
+        let mut targets = Vec::new();
+        for target in &self.target {
+            match parse_line(target) {
+                Ok(parsed) => targets.extend(parsed),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        match self.input.as_deref() {
+            Some("-") => targets.extend(read_targets(io::stdin().lock(), "stdin")),
+            Some(path) => match File::open(path) {
+                Ok(file) => targets.extend(read_targets(BufReader::new(file), path)),
+                Err(e) => eprintln!("{}: {}", path, e),
+            },
+            None if self.target.is_empty() && !io::stdin().is_terminal() => {
+                targets.extend(read_targets(io::stdin().lock(), "stdin"))
+            }
+            None => {}
+        }
+        targets
+    }
 }
 
 fn main() {
     let c = ConfigArgs::new();
-    let host_port: Vec<&str> = c.target.splitn(2, ":").collect();
-    if host_port.len() == 2 {
-        let port = u16::from_str(&host_port[1]).unwrap_or(443);
-        match Scanner::new(host_port[0].to_string(), port) {
-            Ok(s) => {
-                println!("{}", s.fingerprint());
-            }
-            Err(e) => {
-                println!("{:?}", e);
-            }
+    if let Some(Command::Diff(args)) = &c.command {
+        std::process::exit(diff(args, &c));
+    }
+    if let Some(path) = &c.pcap {
+        return offline(path, &c);
+    }
+    let ports = match c.ports.as_deref().map(parse_ports).transpose() {
+        Ok(ports) => ports,
+        Err(e) => {
+            eprintln!("-p: {}", e);
+            std::process::exit(1);
         }
-    } else {
-        match Scanner::new(c.target.to_string(), 443) {
-            Ok(s) => {
-                println!("{}", s.fingerprint());
-            }
+    };
+    // #Every host:port pair, a port on the target line wins over -p
+    let mut jobs = Vec::new();
+    for target in c.targets() {
+        let starttls = target.starttls.or(c.starttls);
+        let target_ports = match (target.port, &ports) {
+            (Some(port), _) => vec![port],
+            (None, Some(ports)) => ports.clone(),
+            (None, None) => vec![starttls.map_or(443, |p| p.default_port())],
+        };
+        for port in target_ports {
+            jobs.push(ScanTarget {
+                port: Some(port),
+                starttls,
+                ..target.clone()
+            });
+        }
+    }
+    let limiter = match c.rate.as_deref().map(parse_rate).transpose() {
+        Ok(rate) => rate.map(RateLimiter::per_second),
+        Err(e) => {
+            eprintln!("--rate: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let delay = Duration::from_millis(c.delay);
+    let db = load_db(&c);
+    let probes = load_probes(&c);
+    let output = Output::new(c.format);
+    // #Workers take the next job until there are none left
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..c.concurrency.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                match jobs.get(index) {
+                    Some(job) => {
+                        let record = scan(job, &db, &probes, limiter.as_ref(), delay, &c);
+                        output.write(index, record)
+                    }
+                    None => break,
+                }
+            });
+        }
+    });
+    output.finish();
+}
+
+// #The bundled database with every --db file on top
+fn load_db(c: &ConfigArgs) -> FingerprintDb {
+    let mut db = FingerprintDb::bundled();
+    for path in &c.db {
+        match FingerprintDb::load(path) {
+            Ok(user) => db.extend(user),
             Err(e) => {
-                println!("{:?}", e);
+                eprintln!("--db {}: {}", path, e);
+                std::process::exit(1);
             }
         }
     }
-}
\ No newline at end of file
+    db
+}
+
+fn load_probes(c: &ConfigArgs) -> ProbeSet {
+    match c.probes.as_deref().map(ProbeSet::load).transpose() {
+        Ok(probes) => probes.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("--probes: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// #Print the scans found in a capture like live results
+fn offline(path: &str, c: &ConfigArgs) {
+    let scans = match Capture::load(path) {
+        Ok(capture) => capture.into_scans(),
+        Err(e) => {
+            eprintln!("--pcap {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let db = load_db(c);
+    let output = Output::new(c.format);
+    for (index, scan) in scans.into_iter().enumerate() {
+        let server = scan.result.addr;
+        let host = server.map(|a| a.ip().to_string()).unwrap_or_default();
+        let port = server.map_or(443, |a| a.port());
+        let mut record = Record::new(&host, port, None, scan.timestamp);
+        record.sni = scan.sni;
+        let mut result = scan.result;
+        db.annotate(&mut result);
+        record.set_result(result);
+        output.write(index, record);
+    }
+    output.finish();
+}
+
+fn scan(
+    job: &ScanTarget,
+    db: &FingerprintDb,
+    probes: &ProbeSet,
+    limiter: Option<&RateLimiter>,
+    delay: Duration,
+    c: &ConfigArgs,
+) -> Record {
+    let port = job.port.unwrap_or(443);
+    let mut record = Record::new(&job.host, port, job.starttls, SystemTime::now());
+    let mut builder = Scanner::builder(job.host.as_str(), port)
+        .probe_delay(delay)
+        .probes(probes.clone())
+        .certificates(c.certs)
+        .full_handshake(c.handshake);
+    if let Some(protocol) = job.starttls {
+        builder = builder.starttls(protocol);
+    }
+    if let Some(limiter) = limiter {
+        builder = builder.rate_limiter(limiter.clone());
+    }
+    match builder.build() {
+        Ok(s) => {
+            record.sni = s.sni().map(String::from);
+            let mut result = s.scan();
+            db.annotate(&mut result);
+            record.set_result(result);
+        }
+        Err(e) => record.set_error(&e),
+    }
+    record
+}
+
+// #Exits like diff(1): 0 when both sides are the same, 1 when they differ, 2 on errors
+fn diff(args: &DiffArgs, c: &ConfigArgs) -> i32 {
+    let db = FingerprintDb::new();
+    let probes = load_probes(c);
+    let side = |arg: &str| -> Result<Side, String> {
+        if std::path::Path::new(arg).is_file() {
+            return Side::load(arg);
+        }
+        let mut targets = parse_line(arg)?;
+        if targets.len() != 1 {
+            return Err(format!("{}: expected one host:port to scan", arg));
+        }
+        let mut target = targets.remove(0);
+        target.starttls = target.starttls.or(c.starttls);
+        target.port = target
+            .port
+            .or(Some(target.starttls.map_or(443, |p| p.default_port())));
+        let delay = Duration::from_millis(c.delay);
+        Ok(Side::from_record(scan(
+            &target, &db, &probes, None, delay, c,
+        )))
+    };
+    let (left, right) = std::thread::scope(|scope| {
+        let left = scope.spawn(|| side(&args.left));
+        let right = side(&args.right);
+        (
+            left.join()
+                .unwrap_or_else(|_| Err("scan panicked".to_string())),
+            right,
+        )
+    });
+    match (left, right) {
+        (Ok(left), Ok(right)) => {
+            let (text, changed) = diff::render(&left, &right, io::stdout().is_terminal());
+            print!("{}", text);
+            changed as i32
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use jarm::{
+    Certificate, Handshake, JarmError, JarmFingerprint, Match, ProbeResult, ScanResult, StartTls,
+};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{hash_column, probes_column, Format, Record};
+    use jarm::{Certificate, JarmError, JarmFingerprint, Match, ProbeResult};
+    use std::str::FromStr;
+
+    fn record() -> Record {
+        Record {
+            host: "::1".to_string(),
+            port: 8443,
+            ip: Some("::1".parse().unwrap()),
+            sni: Some("example.com".to_string()),
+            starttls: None,
+            fingerprint: None,
+            probe_set: None,
+            closed: false,
+            error: None,
+            error_detail: None,
+            timestamp: "2022-10-10T00:00:00Z".to_string(),
+            probes: vec![
+                ProbeResult {
+                    name: "tls1_2_forward".to_string(),
+                    cipher: Some("c02f".to_string()),
+                    version: Some("0303".to_string()),
+                    alpn: Some("h2".to_string()),
+                    extensions: vec!["ff01".to_string(), "0010".to_string()],
+                    ja3s: Some("771,49199,65281-16".to_string()),
+                    ja3s_hash: Some("7bee5c1d424b7e5f943b06983bb11422".to_string()),
+                    ja4s: Some("t1202h2_c02f_87b1562aab70".to_string()),
+                    ja4x: None,
+                    bytes_read: 105,
+                    error: None,
+                },
+                ProbeResult::from_response("tls1_2_reverse", b"\x15\x03\x03\x00\x02\x02\x28"),
+            ],
+            certificate: None,
+            handshake: None,
+            matches: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Format::from_str("ndjson"), Ok(Format::Ndjson));
+        assert!(Format::from_str("xml").is_err());
+        let record = record();
+        assert_eq!(record.target(), "[::1]:8443");
+        assert_eq!(
+            probes_column(&record.probes),
+            "tls1_2_forward:c02f:0303:h2:ff01-0010;tls1_2_reverse:alert"
+        );
+        assert_eq!(
+            hash_column(&record.probes, |p| p.ja3s_hash.as_deref()),
+            "tls1_2_forward:7bee5c1d424b7e5f943b06983bb11422"
+        );
+        assert_eq!(
+            hash_column(&record.probes, |p| p.ja4s.as_deref()),
+            "tls1_2_forward:t1202h2_c02f_87b1562aab70"
+        );
+        assert_eq!(hash_column(&record.probes, |p| p.ja4x.as_deref()), "");
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["port"], 8443);
+        assert_eq!(json["ip"], "::1");
+        assert_eq!(json["probes"][0]["cipher"], "c02f");
+        assert!(json.get("certificate").is_none());
+    }
+
+    #[test]
+    fn error_record() {
+        let mut record = record();
+        record.set_error(&JarmError::Resolve("host.invalid".to_string()));
+        assert_eq!(record.error.as_deref(), Some("resolve"));
+        assert_eq!(record.text(), "[::1]:8443 failed to resolve host.invalid");
+    }
+
+    #[test]
+    fn matches() {
+        let mut record = record();
+        record.fingerprint = Some(JarmFingerprint::zero());
+        record.matches = vec![Match {
+            label: "Metasploit".to_string(),
+            confidence: 70,
+            jarm: JarmFingerprint::zero(),
+        }];
+        assert_eq!(
+            record.text(),
+            format!("[::1]:8443 {} Metasploit (70%)", "0".repeat(62))
+        );
+        record.certificate = Some(Certificate {
+            subject: "CN=example.com, O=Example".to_string(),
+            issuer: "CN=Example CA".to_string(),
+            sans: vec!["example.com".to_string()],
+            not_before: "2022-01-01T00:00:00Z".to_string(),
+            not_after: "2023-01-01T00:00:00Z".to_string(),
+            key_type: "EC".to_string(),
+            key_bits: 256,
+            sha1: "ab".repeat(20),
+            sha256: "cd".repeat(32),
+        });
+        assert_eq!(
+            record.text(),
+            format!(
+                "[::1]:8443 {} {} \"CN=example.com, O=Example\" Metasploit (70%)",
+                "0".repeat(62),
+                "cd".repeat(32)
+            )
+        );
+    }
+}
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// `host:port fingerprint`, one line per target, with the leaf certificate's
+    /// SHA-256 and subject when there is one
+    #[default]
+    Text,
+    /// One JSON array once every scan is done
+    Json,
+    /// One JSON object per line as scans finish
+    Ndjson,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!(
+                "unknown format {:?}, expected json, ndjson, csv or text",
+                s
+            )),
+        }
+    }
+}
+
+/// The outcome of scanning one host:port pair.
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    pub host: String,
+    pub port: u16,
+    /// The address that was scanned
+    pub ip: Option<IpAddr>,
+    pub sni: Option<String>,
+    pub starttls: Option<StartTls>,
+    /// Missing when the scan could not start, e.g. the host did not resolve
+    pub fingerprint: Option<JarmFingerprint>,
+    /// The probe set behind the fingerprint, `jarm` unless `--probes` was given
+    pub probe_set: Option<String>,
+    /// No probe completed a TCP connect
+    pub closed: bool,
+    /// The kind of the error when the whole scan failed, e.g. `read_timeout`
+    pub error: Option<String>,
+    pub error_detail: Option<String>,
+    /// When the scan started, RFC 3339 in UTC
+    pub timestamp: String,
+    pub probes: Vec<ProbeResult>,
+    /// The leaf certificate, read with --certs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<Certificate>,
+    /// The full handshake, with --handshake
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handshake: Option<Handshake>,
+    /// Labels from the fingerprint database
+    pub matches: Vec<Match>,
+}
+
+impl Record {
+    pub fn new(host: &str, port: u16, starttls: Option<StartTls>, started: SystemTime) -> Self {
+        Record {
+            host: host.to_string(),
+            port,
+            ip: None,
+            sni: None,
+            starttls,
+            fingerprint: None,
+            probe_set: None,
+            closed: false,
+            error: None,
+            error_detail: None,
+            timestamp: humantime::format_rfc3339_seconds(started).to_string(),
+            probes: Vec::new(),
+            certificate: None,
+            handshake: None,
+            matches: Vec::new(),
+        }
+    }
+    pub fn set_result(&mut self, result: ScanResult) {
+        if let Some(error) = result.error() {
+            self.set_error(error);
+        }
+        self.ip = result.addr.map(|addr| addr.ip());
+        self.closed = result.is_closed();
+        self.fingerprint = Some(result.fingerprint);
+        self.probe_set = Some(result.probe_set);
+        self.probes = result.probes;
+        self.certificate = result.certificate;
+        self.handshake = result.handshake;
+        self.matches = result.matches;
+    }
+    pub fn set_error(&mut self, error: &JarmError) {
+        self.error = Some(error.kind().to_string());
+        self.error_detail = Some(error.to_string());
+    }
+    pub fn target(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+    // #`host:port` and the fingerprint, `closed` or the error, for any number of targets,
+    // #then the SHA-256 and subject of the leaf certificate
+    // #The certificate read with --certs, or the leaf of the full handshake for TLS 1.3 servers
+    fn leaf(&self) -> Option<&Certificate> {
+        self.certificate
+            .as_ref()
+            .or_else(|| self.handshake.as_ref().and_then(|h| h.certificates.first()))
+    }
+    fn text(&self) -> String {
+        let text = match (&self.fingerprint, &self.error_detail) {
+            _ if self.closed => "closed".to_string(),
+            (Some(fingerprint), _) => fingerprint.to_string(),
+            (None, Some(error)) => error.clone(),
+            (None, None) => String::new(),
+        };
+        let mut text = format!("{} {}", self.target(), text);
+        if let Some(cert) = self.leaf() {
+            let _ = write!(text, " {} {:?}", cert.sha256, cert.subject);
+        }
+        match self.matches.is_empty() {
+            true => text,
+            false => format!("{} {}", text, matches_column(&self.matches)),
+        }
+    }
+}
+
+// #Probes as `name:cipher:version:alpn:extensions`, or `name:error` when the probe failed
+fn probes_column(probes: &[ProbeResult]) -> String {
+    let probe = |p: &ProbeResult| match &p.error {
+        Some(error) => format!("{}:{}", p.name, error.kind()),
+        None => format!(
+            "{}:{}:{}:{}:{}",
+            p.name,
+            p.cipher.as_deref().unwrap_or_default(),
+            p.version.as_deref().unwrap_or_default(),
+            p.alpn.as_deref().unwrap_or_default(),
+            p.extensions.join("-")
+        ),
+    };
+    probes.iter().map(probe).collect::<Vec<_>>().join(";")
+}
+
+// #A per probe hash as `name:hash`, for the probes that have one
+fn hash_column(probes: &[ProbeResult], hash: fn(&ProbeResult) -> Option<&str>) -> String {
+    let hashes: Vec<String> = probes
+        .iter()
+        .filter_map(|p| Some(format!("{}:{}", p.name, hash(p)?)))
+        .collect();
+    hashes.join(";")
+}
+
+// #Labels as `label (confidence%)`, the most confident first
+fn matches_column(matches: &[Match]) -> String {
+    let labels: Vec<String> = matches
+        .iter()
+        .map(|m| format!("{} ({}%)", m.label, m.confidence))
+        .collect();
+    labels.join("; ")
+}
+
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    host: &'a str,
+    port: u16,
+    ip: Option<IpAddr>,
+    sni: Option<&'a str>,
+    starttls: Option<StartTls>,
+    fingerprint: Option<&'a str>,
+    probe_set: Option<&'a str>,
+    closed: bool,
+    error: Option<&'a str>,
+    error_detail: Option<&'a str>,
+    timestamp: &'a str,
+    probes: String,
+    ja3s: String,
+    ja4s: String,
+    ja4x: String,
+    cert_subject: Option<&'a str>,
+    cert_issuer: Option<&'a str>,
+    cert_sans: Option<String>,
+    cert_not_before: Option<&'a str>,
+    cert_not_after: Option<&'a str>,
+    cert_key: Option<String>,
+    cert_sha1: Option<&'a str>,
+    cert_sha256: Option<&'a str>,
+    tls_version: Option<&'a str>,
+    tls_cipher: Option<&'a str>,
+    http_server: Option<&'a str>,
+    ja4h: Option<&'a str>,
+    matches: String,
+}
+
+/// Prints records as scans finish, from any number of worker threads.
+pub struct Output {
+    format: Format,
+    csv: Mutex<csv::Writer<io::Stdout>>,
+    // json collects the records to print them in input order
+    records: Mutex<Vec<(usize, Record)>>,
+}
+
+impl Output {
+    pub fn new(format: Format) -> Self {
+        Output {
+            format,
+            csv: Mutex::new(csv::Writer::from_writer(io::stdout())),
+            records: Mutex::new(Vec::new()),
+        }
+    }
+    pub fn write(&self, index: usize, record: Record) {
+        match self.format {
+            Format::Text => println!("{}", record.text()),
+            Format::Ndjson => {
+                let line = serde_json::to_string(&record).unwrap_or_default();
+                let mut stdout = io::stdout().lock();
+                let _ = writeln!(stdout, "{}", line);
+            }
+            Format::Csv => {
+                let handshake = record.handshake.as_ref();
+                let http = handshake.and_then(|h| h.http.as_ref());
+                let cert = record.leaf();
+                let row = CsvRow {
+                    host: &record.host,
+                    port: record.port,
+                    ip: record.ip,
+                    sni: record.sni.as_deref(),
+                    starttls: record.starttls,
+                    fingerprint: record.fingerprint.as_ref().map(|f| f.as_str()),
+                    probe_set: record.probe_set.as_deref(),
+                    closed: record.closed,
+                    error: record.error.as_deref(),
+                    error_detail: record.error_detail.as_deref(),
+                    timestamp: &record.timestamp,
+                    probes: probes_column(&record.probes),
+                    ja3s: hash_column(&record.probes, |p| p.ja3s_hash.as_deref()),
+                    ja4s: hash_column(&record.probes, |p| p.ja4s.as_deref()),
+                    ja4x: hash_column(&record.probes, |p| p.ja4x.as_deref()),
+                    cert_subject: cert.map(|c| c.subject.as_str()),
+                    cert_issuer: cert.map(|c| c.issuer.as_str()),
+                    cert_sans: cert.map(|c| c.sans.join(";")),
+                    cert_not_before: cert.map(|c| c.not_before.as_str()),
+                    cert_not_after: cert.map(|c| c.not_after.as_str()),
+                    cert_key: cert.map(|c| format!("{} {}", c.key_type, c.key_bits)),
+                    cert_sha1: cert.map(|c| c.sha1.as_str()),
+                    cert_sha256: cert.map(|c| c.sha256.as_str()),
+                    tls_version: handshake.and_then(|h| h.version.as_deref()),
+                    tls_cipher: handshake.and_then(|h| h.cipher.as_deref()),
+                    http_server: http.and_then(|h| h.server.as_deref()),
+                    ja4h: http.map(|h| h.ja4h.as_str()),
+                    matches: matches_column(&record.matches),
+                };
+                let mut csv = self.csv.lock().unwrap_or_else(|e| e.into_inner());
+                if let Err(e) = csv.serialize(row).and_then(|_| Ok(csv.flush()?)) {
+                    eprintln!("csv: {}", e);
+                }
+            }
+            Format::Json => self
+                .records
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push((index, record)),
+        }
+    }
+    pub fn finish(self) {
+        if self.format != Format::Json {
+            return;
+        }
+        let mut records = self.records.into_inner().unwrap_or_else(|e| e.into_inner());
+        records.sort_by_key(|(index, _)| *index);
+        let records: Vec<Record> = records.into_iter().map(|(_, record)| record).collect();
+        match serde_json::to_string_pretty(&records) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("json: {}", e),
+        }
+    }
+}